@@ -0,0 +1,70 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Prefix of the extended attributes on Unix (`user.` is the namespace unprivileged users may write)
+#[cfg(unix)]
+const XATTR_PREFIX: &str = "user.osu-collect.";
+/// Name of the NTFS alternate data stream on Windows
+#[cfg(windows)]
+const ADS_NAME: &str = "osu-collect";
+
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+/// What a downloaded file is, kept with the file so it can be identified after a rename
+pub struct FileMetadata<'a> {
+    pub beatmapset_id: u32,
+    /// Collection source like `collection:17503`
+    pub collection: &'a str,
+    /// URL the file was downloaded from
+    pub source_url: &'a str,
+}
+
+impl FileMetadata<'_> {
+    fn entries(&self) -> [(&'static str, String); 3] {
+        [
+            ("beatmapset_id", self.beatmapset_id.to_string()),
+            ("collection", self.collection.to_string()),
+            ("source_url", self.source_url.to_string()),
+        ]
+    }
+}
+
+/// Store the metadata as xattrs (Unix) or an alternate data stream (NTFS).
+/// Filesystems without support only produce a single warning per run, never a failed download.
+pub fn write(path: &Path, metadata: &FileMetadata<'_>) {
+    if let Err(e) = write_platform(path, metadata)
+        && !WARNED.swap(true, Ordering::Relaxed)
+    {
+        eprintln!(
+            "\x1b[33m⚠\x1b[0m Warning: could not store metadata attributes on {}: {} (not supported by this filesystem?)",
+            path.display(),
+            e
+        );
+    }
+}
+
+#[cfg(unix)]
+fn write_platform(path: &Path, metadata: &FileMetadata<'_>) -> std::io::Result<()> {
+    for (name, value) in metadata.entries() {
+        xattr::set(path, format!("{}{}", XATTR_PREFIX, name), value.as_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn write_platform(path: &Path, metadata: &FileMetadata<'_>) -> std::io::Result<()> {
+    let mut stream = path.as_os_str().to_owned();
+    stream.push(":");
+    stream.push(ADS_NAME);
+
+    let contents: String = metadata.entries()
+        .iter()
+        .map(|(name, value)| format!("{}={}\n", name, value))
+        .collect();
+    std::fs::write(stream, contents)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn write_platform(_path: &Path, _metadata: &FileMetadata<'_>) -> std::io::Result<()> {
+    Err(std::io::Error::from(std::io::ErrorKind::Unsupported))
+}
@@ -0,0 +1,89 @@
+use crate::error::{AppError, Result};
+use crate::utils::{format_timestamp, temp_path};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const BACKUP_EXTENSION: &str = "bak";
+
+/// Copy a file of an osu! install to `<name>.<timestamp>.bak` next to it before it's modified,
+/// keeping only the newest `keep` backups (0 disables backups)
+pub fn create(path: &Path, keep: usize) -> Result<Option<PathBuf>> {
+    if keep == 0 {
+        return Ok(None);
+    }
+
+    let file_name = file_name(path)?;
+    let backup = path.with_file_name(format!("{}.{}.{}", file_name, timestamp(), BACKUP_EXTENSION));
+
+    std::fs::copy(path, &backup).map_err(|e| {
+        AppError::other_dynamic(
+            format!("Failed to back up {}: {}", path.display(), e).into_boxed_str()
+        )
+    })?;
+
+    for old in list(path)?.into_iter().skip(keep) {
+        let _ = std::fs::remove_file(old);
+    }
+
+    Ok(Some(backup))
+}
+
+/// Backups of a file, newest first
+pub fn list(path: &Path) -> Result<Vec<PathBuf>> {
+    let file_name = file_name(path)?;
+    let prefix = format!("{}.", file_name);
+    let suffix = format!(".{}", BACKUP_EXTENSION);
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+
+    let mut backups: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .flatten()
+        .filter(|entry| {
+            entry.file_name()
+                .to_str()
+                .is_some_and(|name| name.starts_with(&prefix) && name.ends_with(&suffix))
+        })
+        .map(|entry| entry.path())
+        .collect();
+
+    // Timestamps sort chronologically as text
+    backups.sort_unstable_by(|a, b| b.cmp(a));
+    Ok(backups)
+}
+
+/// Put a backup back in place of the file, the newest one when `backup` is `None`
+pub fn restore(path: &Path, backup: Option<&Path>) -> Result<PathBuf> {
+    let backup = match backup {
+        Some(backup) => backup.to_path_buf(),
+        None => list(path)?.into_iter().next().ok_or_else(|| {
+            AppError::other_dynamic(format!("No backups of {} found", path.display()).into_boxed_str())
+        })?,
+    };
+
+    let temp_path = temp_path(path);
+    std::fs::copy(&backup, &temp_path)
+        .and_then(|_| std::fs::rename(&temp_path, path))
+        .map_err(|e| {
+            let _ = std::fs::remove_file(&temp_path);
+            AppError::other_dynamic(
+                format!("Failed to restore {} from {}: {}", path.display(), backup.display(), e).into_boxed_str()
+            )
+        })?;
+
+    Ok(backup)
+}
+
+fn file_name(path: &Path) -> Result<&str> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| AppError::other_dynamic(format!("Invalid file path: {}", path.display()).into_boxed_str()))
+}
+
+/// Current UTC time as `YYYYMMDD-HHMMSS.mmm`, safe in file names on every platform
+fn timestamp() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let timestamp = format_timestamp(now.as_secs() as i64)
+        .replace(['-', ':', 'Z'], "")
+        .replace('T', "-");
+
+    format!("{}.{:03}", timestamp, now.subsec_millis())
+}
@@ -0,0 +1,226 @@
+use crate::collection::Layout;
+use crate::collector;
+use crate::error::{AppError, Result};
+use crate::export::ExportFormat;
+use clap::{Args, CommandFactory, Parser};
+use std::path::Path;
+
+/// Settings that can differ between collections of a batch
+#[derive(Args, Debug, Clone, Default)]
+pub struct CollectionOptions {
+    /// Only download beatmapsets added to the collection on or after this date (YYYY-MM-DD)
+    #[arg(long, value_name = "DATE", value_parser = collector::parse_date)]
+    pub added_after: Option<String>,
+
+    /// Only put difficulties whose name contains this text into collection.db (can be repeated)
+    #[arg(long, value_name = "TEXT")]
+    pub diff_name_contains: Vec<String>,
+
+    /// Leave difficulties whose name contains this text out of collection.db (can be repeated)
+    #[arg(long, value_name = "TEXT")]
+    pub exclude_diff_name: Vec<String>,
+
+    /// Only keep difficulties matching a mode (osu, taiko, catch, mania), a mania key count
+    /// (e.g. 4k) or a name text, comma-separated or repeated. Sets without a matching difficulty
+    /// aren't downloaded
+    #[arg(long, value_name = "FILTER", value_delimiter = ',', value_parser = collector::parse_diff_filter)]
+    pub only_diffs: Vec<collector::DiffFilter>,
+
+    /// Detect sets sharing artist and title, list them and only download the highest-status one.
+    /// The difficulties of the other sets are left out of collection.db too
+    #[arg(long)]
+    pub dedupe_titles: bool,
+
+    /// Retry beatmapsets previously not found on any mirror
+    #[arg(long)]
+    pub recheck_unavailable: bool,
+
+    /// Also write the collection's beatmap checksums (can be repeated)
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub export: Vec<ExportFormat>,
+
+    /// Organize downloaded files into subfolders [default: flat]
+    #[arg(long, value_enum)]
+    pub layout: Option<Layout>,
+}
+
+impl CollectionOptions {
+    /// Whether an option reads difficulty names, star ratings, artist, title or status,
+    /// which osu!collector collections only provide through `collector::fetch_collection_metadata`
+    pub fn needs_metadata(&self) -> bool {
+        !self.diff_name_contains.is_empty()
+            || !self.exclude_diff_name.is_empty()
+            || !self.only_diffs.is_empty()
+            || self.dedupe_titles
+            || self.layout.is_some_and(|layout| layout != Layout::Flat)
+    }
+
+    /// Options of a list line layered over the command line ones
+    fn overriding(&self, line: LineOptions) -> Self {
+        let LineOptions { options: line, no_dedupe_titles, no_recheck_unavailable } = line;
+
+        CollectionOptions {
+            added_after: line.added_after.or_else(|| self.added_after.clone()),
+            diff_name_contains: non_empty_or(line.diff_name_contains, &self.diff_name_contains),
+            exclude_diff_name: non_empty_or(line.exclude_diff_name, &self.exclude_diff_name),
+            only_diffs: non_empty_or(line.only_diffs, &self.only_diffs),
+            dedupe_titles: !no_dedupe_titles && (line.dedupe_titles || self.dedupe_titles),
+            recheck_unavailable: !no_recheck_unavailable && (line.recheck_unavailable || self.recheck_unavailable),
+            export: non_empty_or(line.export, &self.export),
+            layout: line.layout.or(self.layout),
+        }
+    }
+}
+
+#[inline]
+fn non_empty_or<T: Clone>(line: Vec<T>, base: &[T]) -> Vec<T> {
+    if line.is_empty() { base.to_vec() } else { line }
+}
+
+/// Options accepted on a line of a collection list
+#[derive(Parser)]
+#[command(no_binary_name = true, disable_help_flag = true)]
+struct LineOptions {
+    #[command(flatten)]
+    options: CollectionOptions,
+
+    /// Turn off a `--dedupe-titles` given on the command line for this collection
+    #[arg(long, conflicts_with = "dedupe_titles")]
+    no_dedupe_titles: bool,
+
+    /// Turn off a `--recheck-unavailable` given on the command line for this collection
+    #[arg(long, conflicts_with = "recheck_unavailable")]
+    no_recheck_unavailable: bool,
+}
+
+/// A collection of the list with its effective options
+pub struct BatchEntry {
+    pub input: String,
+    pub options: CollectionOptions,
+}
+
+/// Read collection URLs or IDs from a list file, one per line, optionally followed by
+/// per-collection options (`<url> --added-after 2024-01-01 --dedupe-titles`).
+/// Blank lines are ignored and `#` at the start of a word (outside quotes) starts a comment,
+/// so URLs with a `#fragment` are kept whole.
+pub fn read_collection_list(path: &Path, defaults: &CollectionOptions) -> Result<Vec<BatchEntry>> {
+    let contents = std::fs::read_to_string(path).map_err(|e| {
+        AppError::other_dynamic(
+            format!("Failed to read collection list '{}': {}", path.display(), e).into_boxed_str()
+        )
+    })?;
+
+    let mut collections = Vec::new();
+
+    for (index, line) in contents.lines().enumerate() {
+        let line_error = |msg: String| AppError::other_dynamic(
+            format!("{}:{}: {}", path.display(), index + 1, msg).into_boxed_str()
+        );
+
+        let mut words = split_words(line).map_err(|e| line_error(e.to_string()))?.into_iter();
+        let Some(input) = words.next() else {
+            continue;
+        };
+
+        let options = LineOptions::try_parse_from(words).map_err(|e| {
+            let rendered = e.to_string();
+            let message = rendered.lines().next().unwrap_or_default().trim_start_matches("error: ");
+
+            if e.kind() == clap::error::ErrorKind::UnknownArgument {
+                line_error(format!("{}, supported options: {}", message, supported_options()))
+            } else {
+                line_error(message.to_string())
+            }
+        })?;
+
+        collections.push(BatchEntry {
+            input,
+            options: defaults.overriding(options),
+        });
+    }
+
+    if collections.is_empty() {
+        return Err(AppError::other_dynamic(
+            format!("Collection list '{}' is empty", path.display()).into_boxed_str()
+        ));
+    }
+
+    Ok(collections)
+}
+
+/// Long names of the options a list line accepts, for error messages
+fn supported_options() -> String {
+    let command = LineOptions::command();
+    let names: Vec<String> = command.get_arguments()
+        .filter_map(|arg| arg.get_long())
+        .map(|long| format!("--{}", long))
+        .collect();
+
+    names.join(", ")
+}
+
+/// Split a line into words, keeping text in single or double quotes together.
+/// A `#` starting a word outside quotes ends the line.
+fn split_words(line: &str) -> std::result::Result<Vec<String>, &'static str> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, '#') if !in_word => break,
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+
+    if quote.is_some() {
+        return Err("unterminated quote");
+    }
+    if in_word {
+        words.push(word);
+    }
+
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_words_keeps_quoted_text_together() {
+        assert_eq!(
+            split_words(r#"123 --output "my songs" --tag 'it''s'"#).unwrap(),
+            ["123", "--output", "my songs", "--tag", "its"]
+        );
+        assert_eq!(split_words(r#"--name "" 5"#).unwrap(), ["--name", "", "5"]);
+    }
+
+    #[test]
+    fn split_words_stops_at_comments() {
+        assert_eq!(split_words("123 --profile # weekly pool").unwrap(), ["123", "--profile"]);
+        assert_eq!(split_words("# only a comment").unwrap(), Vec::<String>::new());
+        assert_eq!(split_words(r##"123 --tag "#1" tag#2"##).unwrap(), ["123", "--tag", "#1", "tag#2"]);
+    }
+
+    #[test]
+    fn split_words_rejects_unterminated_quotes() {
+        assert!(split_words("123 --output \"my songs").is_err());
+    }
+}
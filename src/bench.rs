@@ -0,0 +1,72 @@
+use crate::error::Result;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+
+/// Size of each benchmark file, about a large beatmapset
+const BENCH_FILE_SIZE: usize = 32 * 1024 * 1024;
+/// Written in chunks the size of typical network reads, like a real download
+const BENCH_CHUNK_SIZE: usize = 64 * 1024;
+/// Rough per-download speed of a good mirror connection
+const EXPECTED_DOWNLOAD_SPEED: f64 = 5.0 * 1024.0 * 1024.0;
+
+/// Measure write throughput of `dir` with one writer and with `concurrent` writers,
+/// warning when the disk can't keep up with the configured concurrency
+pub async fn run(dir: &Path, concurrent: u8) -> Result<()> {
+    println!("Benchmarking writes to {}", dir.display());
+    println!("(writing {} MB per file, temporary files are removed afterwards)\n", BENCH_FILE_SIZE / 1024 / 1024);
+
+    let single = measure(dir, 1).await?;
+    println!("Sequential write, 1 file:  {:>8.1} MB/s", single / 1024.0 / 1024.0);
+
+    let parallel = if concurrent > 1 {
+        let speed = measure(dir, concurrent).await?;
+        println!("Parallel write, {} files: {:>8.1} MB/s", concurrent, speed / 1024.0 / 1024.0);
+        speed
+    } else {
+        single
+    };
+
+    let needed = EXPECTED_DOWNLOAD_SPEED * concurrent as f64;
+    println!("\nNeeded for {} concurrent downloads: ~{:.1} MB/s", concurrent, needed / 1024.0 / 1024.0);
+
+    if parallel < needed {
+        println!("\x1b[33m⚠\x1b[0m The disk is likely the bottleneck at this concurrency (common with SMR drives and network shares).");
+        println!("  Lower download.concurrent or download to a faster disk and move the files afterwards.");
+    } else {
+        println!("\x1b[32m✓\x1b[0m The disk should keep up, the network will be the bottleneck.");
+    }
+
+    Ok(())
+}
+
+/// Write one benchmark file per writer at the same time, returns the combined bytes per second
+async fn measure(dir: &Path, writers: u8) -> Result<f64> {
+    let paths: Vec<PathBuf> = (0..writers)
+        .map(|i| dir.join(format!(".osu-collect-bench-{}.tmp", i)))
+        .collect();
+
+    let started = Instant::now();
+    let results = futures_util::future::join_all(paths.iter().map(|path| write_file(path))).await;
+    let elapsed = started.elapsed().max(Duration::from_millis(1));
+
+    for path in &paths {
+        let _ = tokio::fs::remove_file(path).await;
+    }
+    results.into_iter().collect::<Result<Vec<()>>>()?;
+
+    Ok(BENCH_FILE_SIZE as f64 * writers as f64 / elapsed.as_secs_f64())
+}
+
+async fn write_file(path: &Path) -> Result<()> {
+    let chunk = vec![0x5a_u8; BENCH_CHUNK_SIZE];
+    let mut file = tokio::fs::File::create(path).await?;
+
+    for _ in 0..BENCH_FILE_SIZE / BENCH_CHUNK_SIZE {
+        file.write_all(&chunk).await?;
+    }
+
+    // Include the flush to the device, otherwise only the page cache is measured
+    file.sync_all().await?;
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use crate::collector::{BeatmapMetadata, Collection};
+use crate::error::Result;
+use crate::utils::write_atomic;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const CACHE_VERSION: u32 = 1;
+/// Age after which cached metadata is fetched again, statuses and star ratings do change
+const MAX_AGE_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Difficulty names, star ratings, artist, title and status fetched with
+/// `collector::fetch_collection_metadata`, kept by beatmap ID across runs and collections
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MetadataCache {
+    version: u32,
+    #[serde(default)]
+    beatmaps: BTreeMap<u32, CachedBeatmap>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedBeatmap {
+    /// When the metadata was fetched, in seconds since the unix epoch
+    fetched: u64,
+    metadata: BeatmapMetadata,
+}
+
+impl Default for MetadataCache {
+    fn default() -> Self {
+        MetadataCache { version: CACHE_VERSION, beatmaps: BTreeMap::new() }
+    }
+}
+
+/// `~/.cache/osu-collect/beatmaps.json` (platform cache dir elsewhere)
+pub fn cache_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("osu-collect").join("beatmaps.json"))
+}
+
+impl MetadataCache {
+    /// Load the cache, a missing, unreadable or outdated one starts empty
+    pub fn load(path: &Path) -> Self {
+        std::fs::read_to_string(path).ok()
+            .and_then(|contents| serde_json::from_str::<MetadataCache>(&contents).ok())
+            .filter(|cache| cache.version == CACHE_VERSION)
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        write_atomic(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Metadata of every beatmap in the collection, or `None` when any of them isn't cached.
+    /// Entries older than `MAX_AGE_SECS` or with a different checksum (the difficulty was
+    /// updated) don't count.
+    pub fn lookup(&self, collection: &Collection, now: u64) -> Option<Vec<BeatmapMetadata>> {
+        let mut metadata = Vec::new();
+
+        for beatmap in collection.beatmapsets.iter().flat_map(|beatmapset| &beatmapset.beatmaps) {
+            let cached = self.beatmaps.get(&beatmap.id)?;
+            let outdated = cached.metadata.checksum.as_ref().is_some_and(|checksum| *checksum != beatmap.checksum);
+            if outdated || now.saturating_sub(cached.fetched) > MAX_AGE_SECS {
+                return None;
+            }
+            metadata.push(cached.metadata.clone());
+        }
+
+        Some(metadata)
+    }
+
+    /// Store metadata freshly fetched for a collection and drop expired entries. Beatmaps the
+    /// listing left out (e.g. deleted difficulties) are cached without metadata, so they don't
+    /// keep the collection from being served from the cache.
+    pub fn insert(&mut self, collection: &Collection, metadata: &[BeatmapMetadata], now: u64) {
+        self.beatmaps.retain(|_, cached| now.saturating_sub(cached.fetched) <= MAX_AGE_SECS);
+
+        for beatmap in collection.beatmapsets.iter().flat_map(|beatmapset| &beatmapset.beatmaps) {
+            let empty = BeatmapMetadata {
+                id: beatmap.id,
+                checksum: Some(beatmap.checksum.clone()),
+                version: None,
+                difficulty_rating: None,
+                mode: None,
+                cs: None,
+                beatmapset: None,
+            };
+            self.beatmaps.insert(beatmap.id, CachedBeatmap { fetched: now, metadata: empty });
+        }
+        for beatmap in metadata {
+            self.beatmaps.insert(beatmap.id, CachedBeatmap { fetched: now, metadata: beatmap.clone() });
+        }
+    }
+}
+
+/// Current time in seconds since the unix epoch, as cache entries record it
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collection(beatmaps: &[(u32, &str)]) -> Collection {
+        serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "name": "Test",
+            "uploader": { "id": 2, "username": "someone" },
+            "beatmapsets": [{
+                "id": 10,
+                "beatmaps": beatmaps.iter()
+                    .map(|(id, checksum)| serde_json::json!({ "id": id, "checksum": checksum }))
+                    .collect::<Vec<_>>(),
+            }],
+        })).unwrap()
+    }
+
+    fn metadata(id: u32, checksum: &str, stars: f64) -> BeatmapMetadata {
+        serde_json::from_value(serde_json::json!({ "id": id, "checksum": checksum, "difficulty_rating": stars })).unwrap()
+    }
+
+    #[test]
+    fn lookup_needs_every_beatmap_fresh_and_unchanged() {
+        let mut cache = MetadataCache::default();
+        let first = collection(&[(1, "aa"), (2, "bb")]);
+        assert!(cache.lookup(&first, 100).is_none());
+
+        // Beatmap 2 isn't listed, it's still cached so the collection is served from the cache
+        cache.insert(&first, &[metadata(1, "aa", 5.5)], 100);
+        let found = cache.lookup(&first, 100 + MAX_AGE_SECS).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].difficulty_rating, Some(5.5));
+        assert_eq!(found[1].difficulty_rating, None);
+
+        // Expired
+        assert!(cache.lookup(&first, 101 + MAX_AGE_SECS).is_none());
+        // Updated difficulty
+        assert!(cache.lookup(&collection(&[(1, "cc")]), 100).is_none());
+        // Beatmap never fetched
+        assert!(cache.lookup(&collection(&[(1, "aa"), (3, "dd")]), 100).is_none());
+    }
+
+    #[test]
+    fn insert_drops_expired_entries() {
+        let mut cache = MetadataCache::default();
+        cache.insert(&collection(&[(1, "aa")]), &[], 100);
+        cache.insert(&collection(&[(2, "bb")]), &[], 101 + MAX_AGE_SECS);
+        assert_eq!(cache.beatmaps.keys().copied().collect::<Vec<_>>(), [2]);
+    }
+
+    #[test]
+    fn save_and_load_roundtrip() {
+        let path = std::env::temp_dir()
+            .join(format!("osu-collect-cache-{}", std::process::id()))
+            .join("beatmaps.json");
+
+        let mut cache = MetadataCache::default();
+        let first = collection(&[(1, "aa")]);
+        cache.insert(&first, &[metadata(1, "aa", 4.0)], 100);
+        cache.save(&path).unwrap();
+
+        let loaded = MetadataCache::load(&path);
+        assert_eq!(loaded.lookup(&first, 100).unwrap()[0].difficulty_rating, Some(4.0));
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(MetadataCache::load(&path).beatmaps.is_empty());
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
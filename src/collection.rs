@@ -1,45 +1,31 @@
-use crate::collector::Collection;
+use crate::collector::{Beatmapset, Collection};
+use crate::config::CollectionDbConfig;
 use crate::error::{AppError, Result};
-use crate::utils::sanitize_filename;
+use crate::options::DedupePolicy;
+use crate::utils::{normalize_name, sanitize_filename, temp_path, write_atomic};
 use osu_db::collection::{Collection as DbCollection, CollectionList};
-use std::path::Path;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 
 const OSU_DB_VERSION: u32 = 20150203;
+/// Collection metadata as fetched, kept in the collection folder for offline tools
+pub const COLLECTION_FILE_NAME: &str = "collection.json";
 
-/// Create collection.db file from collection data
+/// Create collection.db file from collection data.
+///
+/// Hashes are written in the collection's original order (osu! keeps insertion order,
+/// and curators often order pools by slot), filters only ever remove entries.
 pub fn create_collection_db(
     collection: &Collection,
     collection_name: &str,
     output_dir: &Path,
+    options: &CollectionDbConfig,
 ) -> Result<()> {
-    let db_path = output_dir.join("collection.db");
-
-    let beatmap_hashes: Vec<Option<String>> = collection
-        .beatmapsets
-        .iter()
-        .flat_map(|beatmapset| {
-            beatmapset
-                .beatmaps
-                .iter()
-                .map(|beatmap| Some(beatmap.checksum.to_string()))
-        })
-        .collect();
-
-    let db_collection = DbCollection {
-        name: Some(collection_name.to_string()),
-        beatmap_hashes,
-    };
-
     let collection_list = CollectionList {
         version: OSU_DB_VERSION,
-        collections: vec![db_collection],
+        collections: build_collections(collection, collection_name, options),
     };
-
-    collection_list.to_file(&db_path).map_err(|e| {
-        AppError::other_dynamic(
-            format!("Failed to write collection.db: {}", e).into_boxed_str()
-        )
-    })?;
+    write_collection_list(&collection_list, &output_dir.join("collection.db"))?;
 
     let cfg_path = output_dir.join("osu!.name.cfg");
     std::fs::write(&cfg_path, "").map_err(|e| {
@@ -51,9 +37,510 @@ pub fn create_collection_db(
     Ok(())
 }
 
+/// Changes merging a collection into an existing collection.db makes
+pub struct MergePreview {
+    /// Name and beatmap count of entries that don't exist yet
+    pub added: Vec<(String, usize)>,
+    /// Name and beatmap count before and after of entries that are replaced
+    pub updated: Vec<(String, usize, usize)>,
+    /// Name and beatmap count of entries of the collection that are no longer written, e.g.
+    /// numbered parts of an earlier split at a different size
+    pub removed: Vec<(String, usize)>,
+    /// Entry count before and after
+    pub collections: (usize, usize),
+    /// Beatmap count over all entries before and after
+    pub hashes: (usize, usize),
+}
+
+/// Merge a collection into an existing collection.db (e.g. the one of an osu! install) in
+/// memory. Entries with the same name, or its numbered parts `Name (N)` from splitting, are
+/// replaced, others are kept untouched. Nothing is
+/// written, pass the returned list to `write_collection_list` once the preview is confirmed.
+pub fn prepare_merge(
+    collection: &Collection,
+    collection_name: &str,
+    db_path: &Path,
+    options: &CollectionDbConfig,
+) -> Result<(CollectionList, MergePreview)> {
+    let mut list = CollectionList::from_file(db_path).map_err(|e| {
+        AppError::other_dynamic(
+            format!("Failed to read {}: {}", db_path.display(), e).into_boxed_str()
+        )
+    })?;
+
+    let count = |list: &CollectionList| list.collections.iter().map(|c| c.beatmap_hashes.len()).sum::<usize>();
+    let before = (list.collections.len(), count(&list));
+
+    let bases = unsplit_collections(collection, collection_name).into_iter()
+        .filter_map(|entry| entry.name)
+        .collect::<Vec<_>>();
+    let (added, updated, removed) = merge_entries(
+        &mut list.collections,
+        &bases,
+        build_collections(collection, collection_name, options),
+    );
+
+    let preview = MergePreview {
+        added,
+        updated,
+        removed,
+        collections: (before.0, list.collections.len()),
+        hashes: (before.1, count(&list)),
+    };
+
+    Ok((list, preview))
+}
+
+type MergeChanges = (Vec<(String, usize)>, Vec<(String, usize, usize)>, Vec<(String, usize)>);
+
+/// Replace the existing entries named like one of `bases` (or a numbered part of it) with
+/// `entries`. Entries keep their position when their name is written again, stale parts are
+/// dropped and new ones appended. Returns the added, updated and removed entries.
+fn merge_entries(existing: &mut Vec<DbCollection>, bases: &[String], entries: Vec<DbCollection>) -> MergeChanges {
+    let mut added = Vec::new();
+    let mut updated = Vec::new();
+    let mut removed = Vec::new();
+    let mut entries = entries.into_iter().map(Some).collect::<Vec<_>>();
+
+    existing.retain_mut(|old| {
+        let name = old.name.clone().unwrap_or_default();
+        if !bases.iter().any(|base| is_part_of(&name, base)) {
+            return true;
+        }
+
+        match entries.iter_mut().find(|entry| entry.as_ref().is_some_and(|entry| entry.name == old.name)) {
+            Some(entry) => {
+                let entry = entry.take().unwrap();
+                updated.push((name, old.beatmap_hashes.len(), entry.beatmap_hashes.len()));
+                *old = entry;
+                true
+            }
+            None => {
+                removed.push((name, old.beatmap_hashes.len()));
+                false
+            }
+        }
+    });
+
+    for entry in entries.into_iter().flatten() {
+        added.push((entry.name.clone().unwrap_or_default(), entry.beatmap_hashes.len()));
+        existing.push(entry);
+    }
+
+    (added, updated, removed)
+}
+
+/// Whether `name` is `base` or one of the `base (N)` parts `split_collections` names
+fn is_part_of(name: &str, base: &str) -> bool {
+    name == base
+        || name.strip_prefix(base)
+            .and_then(|rest| rest.strip_prefix(" ("))
+            .and_then(|rest| rest.strip_suffix(')'))
+            .is_some_and(|number| !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Write a collection.db next to the old file and rename it over it, so a crash keeps
+/// the previous db intact
+pub fn write_collection_list(collection_list: &CollectionList, db_path: &Path) -> Result<()> {
+    let temp_path = temp_path(db_path);
+    collection_list.to_file(&temp_path)
+        .map_err(|e| e.to_string())
+        .and_then(|()| std::fs::rename(&temp_path, db_path).map_err(|e| e.to_string()))
+        .map_err(|e| {
+            let _ = std::fs::remove_file(&temp_path);
+            AppError::other_dynamic(
+                format!("Failed to write {}: {}", db_path.display(), e).into_boxed_str()
+            )
+        })
+}
+
+/// Entries written for a collection, after deduplication and splitting
+fn build_collections(collection: &Collection, collection_name: &str, options: &CollectionDbConfig) -> Vec<DbCollection> {
+    let collections = unsplit_collections(collection, collection_name);
+    split_collections(dedupe_hashes(collections, options.dedupe), options.split_at)
+}
+
+/// Entries of a collection as named in osu!, before deduplication and splitting
+fn unsplit_collections(collection: &Collection, collection_name: &str) -> Vec<DbCollection> {
+    if collection.is_tournament() {
+        // One entry per round, named like "TourneyName RO16"
+        collection.rounds.iter()
+            .map(|round| DbCollection {
+                name: Some(format!("{} {}", collection.name, round.name)),
+                beatmap_hashes: round.slots.iter()
+                    .map(|slot| Some(slot.checksum.to_string()))
+                    .collect(),
+            })
+            .collect()
+    } else {
+        let beatmap_hashes: Vec<Option<String>> = collection
+            .beatmapsets
+            .iter()
+            .flat_map(|beatmapset| {
+                beatmapset
+                    .beatmaps
+                    .iter()
+                    .map(|beatmap| Some(beatmap.checksum.to_string()))
+            })
+            .collect();
+
+        vec![DbCollection {
+            name: Some(collection_name.to_string()),
+            beatmap_hashes,
+        }]
+    }
+}
+
+/// Drop repeated hashes across all entries according to `policy`.
+/// Runs before splitting so numbered parts never repeat each other either.
+fn dedupe_hashes(mut collections: Vec<DbCollection>, policy: DedupePolicy) -> Vec<DbCollection> {
+    if policy == DedupePolicy::KeepAll {
+        return collections;
+    }
+
+    let mut seen = HashSet::new();
+    for collection in &mut collections {
+        collection.beatmap_hashes.retain(|hash| match hash {
+            Some(hash) => seen.insert(hash.clone()),
+            None => true,
+        });
+    }
+
+    // Entries left empty (e.g. a round fully reusing earlier maps) are dropped
+    collections.retain(|collection| !collection.beatmap_hashes.is_empty());
+    collections
+}
+
+/// Save the fetched collection next to the downloads, so `export-subset` works offline
+pub fn save_collection_metadata(collection: &Collection, output_dir: &Path) -> Result<()> {
+    let contents = serde_json::to_string(collection)?;
+    write_atomic(&output_dir.join(COLLECTION_FILE_NAME), contents).map_err(|e| {
+        AppError::other_dynamic(
+            format!("Failed to write {}: {}", COLLECTION_FILE_NAME, e).into_boxed_str()
+        )
+    })
+}
+
+/// Load the collection saved by `save_collection_metadata`
+pub fn load_collection_metadata(output_dir: &Path) -> Result<Collection> {
+    let path = output_dir.join(COLLECTION_FILE_NAME);
+    let contents = std::fs::read_to_string(&path).map_err(|e| {
+        AppError::other_dynamic(
+            format!("Failed to read {}: {} (download the collection once with this version)", path.display(), e)
+                .into_boxed_str()
+        )
+    })?;
+
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// Whether a folder holds an earlier download of this collection, so downloading into it
+/// resumes that download. Only the collection.json saved by that download tells: the folder
+/// name always ends in the collection ID, and a manifest or state file may be another collection's.
+pub fn is_previous_download(dir: &Path, collection: &Collection) -> bool {
+    load_collection_metadata(dir).is_ok_and(|saved| saved.id == collection.id)
+}
+
+/// Split entries with more than `split_at` hashes into "Name (1)", "Name (2)", ...
+/// since huge collections make osu! stable's collection UI crawl. `0` disables splitting.
+fn split_collections(collections: Vec<DbCollection>, split_at: usize) -> Vec<DbCollection> {
+    if split_at == 0 {
+        return collections;
+    }
+
+    collections
+        .into_iter()
+        .flat_map(|collection| {
+            if collection.beatmap_hashes.len() <= split_at {
+                return vec![collection];
+            }
+
+            let name = collection.name.unwrap_or_default();
+            collection.beatmap_hashes
+                .chunks(split_at)
+                .enumerate()
+                .map(|(index, hashes)| DbCollection {
+                    name: Some(format!("{} ({})", name, index + 1)),
+                    beatmap_hashes: hashes.to_vec(),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// How downloaded files are organized inside the collection folder
+#[derive(Debug, Clone, Copy, PartialEq, Default, clap::ValueEnum)]
+pub enum Layout {
+    /// Every file directly in the collection folder
+    #[default]
+    Flat,
+    /// `Artist/`
+    ByArtist,
+    /// `ranked/`, `loved/`, `graveyard/`, ...
+    ByStatus,
+    /// `5-6 stars/` by the hardest difficulty
+    BySr,
+}
+
+impl Layout {
+    /// Subfolder a beatmapset goes to, sets without the needed metadata go to `Unknown/`
+    pub fn subfolder(self, beatmapset: &Beatmapset) -> Option<String> {
+        if self == Layout::Flat {
+            return None;
+        }
+
+        Some(self.metadata_folder(beatmapset).unwrap_or_else(|| "Unknown".to_string()))
+    }
+
+    /// Folder named after the set's artist, status or star rating, `None` when the set lacks it
+    pub fn metadata_folder(self, beatmapset: &Beatmapset) -> Option<String> {
+        match self {
+            Layout::Flat => None,
+            Layout::ByArtist => beatmapset.artist.as_deref()
+                .map(|artist| sanitize_filename(&normalize_name(artist)))
+                .filter(|artist| !artist.is_empty() && !artist.starts_with('.')),
+            Layout::ByStatus => beatmapset.status_name().map(str::to_string),
+            Layout::BySr => beatmapset.max_difficulty().map(|sr| {
+                let stars = sr.max(0.0).floor() as u32;
+                format!("{}-{} stars", stars, stars + 1)
+            }),
+        }
+    }
+}
+
 /// Generate collection folder name
 #[inline]
 pub fn generate_collection_folder_name(collection: &Collection) -> String {
-    let sanitized_name = sanitize_filename(&collection.name);
-    format!("{}-{}", sanitized_name, collection.id)
+    let sanitized_name = sanitize_filename(&normalize_name(&collection.name));
+    if collection.is_tournament() {
+        format!("{}-tournament-{}", sanitized_name, collection.id)
+    } else {
+        format!("{}-{}", sanitized_name, collection.id)
+    }
+}
+
+/// Files already in a folder, shown before downloading into it
+pub struct FolderSummary {
+    pub files: usize,
+    pub bytes: u64,
+    /// Newest modification time in seconds since the unix epoch
+    pub last_modified: u64,
+}
+
+/// Summarize the files in a folder and its subfolders, `None` when it's missing or empty
+pub fn summarize_folder(dir: &Path) -> Option<FolderSummary> {
+    let mut summary = FolderSummary { files: 0, bytes: 0, last_modified: 0 };
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let Ok(metadata) = entry.metadata() else { continue };
+
+            if metadata.is_dir() {
+                pending.push(entry.path());
+                continue;
+            }
+
+            summary.files += 1;
+            summary.bytes += metadata.len();
+            let modified = metadata.modified().ok()
+                .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_secs());
+            summary.last_modified = summary.last_modified.max(modified);
+        }
+    }
+
+    (summary.files > 0).then_some(summary)
+}
+
+/// Find the folder of a collection in `base_dir`, reusing an existing folder whose name
+/// only differs by Unicode normalization or invisible characters (e.g. created on another OS)
+pub fn find_collection_folder(base_dir: &Path, folder_name: &str) -> PathBuf {
+    let exact = base_dir.join(folder_name);
+    if exact.is_dir() {
+        return exact;
+    }
+
+    std::fs::read_dir(base_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .find(|entry| {
+            entry.file_name()
+                .to_str()
+                .is_some_and(|name| normalize_name(name) == folder_name)
+        })
+        .map(|entry| entry.path())
+        .unwrap_or(exact)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, hashes: &[&str]) -> DbCollection {
+        DbCollection {
+            name: Some(name.to_string()),
+            beatmap_hashes: hashes.iter().map(|hash| Some(hash.to_string())).collect(),
+        }
+    }
+
+    fn summary(collections: &[DbCollection]) -> Vec<(String, Vec<String>)> {
+        collections.iter()
+            .map(|collection| (
+                collection.name.clone().unwrap_or_default(),
+                collection.beatmap_hashes.iter().flatten().cloned().collect(),
+            ))
+            .collect()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("osu-collect-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn collection(id: u32) -> Collection {
+        serde_json::from_value(serde_json::json!({
+            "id": id, "name": "Test", "uploader": { "id": 1, "username": "someone" }, "beatmapsets": []
+        })).unwrap()
+    }
+
+    #[test]
+    fn nonempty_folder_without_matching_metadata_is_not_resumed() {
+        let dir = temp_dir("foreign").join("Test-5");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("1 Artist - Title.osz"), b"PK").unwrap();
+        std::fs::write(dir.join("manifest.json"), "{}").unwrap();
+        std::fs::write(dir.join(".osu-collect.json"), "{}").unwrap();
+
+        assert!(!is_previous_download(&dir, &collection(5)));
+        assert_eq!(summarize_folder(&dir).map(|summary| summary.files), Some(3));
+
+        save_collection_metadata(&collection(6), &dir).unwrap();
+        assert!(!is_previous_download(&dir, &collection(5)));
+
+        std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn folder_with_matching_metadata_is_resumed() {
+        let dir = temp_dir("resume");
+        save_collection_metadata(&collection(5), &dir).unwrap();
+
+        assert!(is_previous_download(&dir, &collection(5)));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn split_collections_numbers_parts() {
+        let split = split_collections(vec![entry("Big", &["a", "b", "c", "d", "e"]), entry("Small", &["f"])], 2);
+
+        assert_eq!(summary(&split), [
+            ("Big (1)".to_string(), vec!["a".to_string(), "b".to_string()]),
+            ("Big (2)".to_string(), vec!["c".to_string(), "d".to_string()]),
+            ("Big (3)".to_string(), vec!["e".to_string()]),
+            ("Small".to_string(), vec!["f".to_string()]),
+        ]);
+    }
+
+    #[test]
+    fn split_collections_keeps_entries_at_limit_or_disabled() {
+        let entries = || vec![entry("Pool", &["a", "b"])];
+
+        assert_eq!(summary(&split_collections(entries(), 2)), summary(&entries()));
+        assert_eq!(summary(&split_collections(entries(), 0)), summary(&entries()));
+    }
+
+    #[test]
+    fn merge_replaces_split_parts() {
+        let mut existing = vec![
+            entry("Mine", &["x"]),
+            entry("Pool (1)", &["a", "b"]),
+            entry("Pool (2)", &["c", "d"]),
+            entry("Pool (3)", &["e"]),
+            entry("Pool (old)", &["y"]),
+        ];
+        let entries = vec![entry("Pool (1)", &["a", "b", "c"]), entry("Pool (2)", &["d", "e"])];
+
+        let (added, updated, removed) = merge_entries(&mut existing, &["Pool".to_string()], entries);
+
+        assert!(added.is_empty());
+        assert_eq!(updated, [("Pool (1)".to_string(), 2, 3), ("Pool (2)".to_string(), 2, 2)]);
+        assert_eq!(removed, [("Pool (3)".to_string(), 1)]);
+        assert_eq!(summary(&existing), summary(&[
+            entry("Mine", &["x"]),
+            entry("Pool (1)", &["a", "b", "c"]),
+            entry("Pool (2)", &["d", "e"]),
+            entry("Pool (old)", &["y"]),
+        ]));
+    }
+
+    #[test]
+    fn merge_replaces_parts_with_unsplit_entry() {
+        let mut existing = vec![entry("Pool (1)", &["a"]), entry("Pool (2)", &["b"])];
+
+        let (added, updated, removed) = merge_entries(&mut existing, &["Pool".to_string()], vec![entry("Pool", &["a", "b"])]);
+
+        assert_eq!(added, [("Pool".to_string(), 2)]);
+        assert!(updated.is_empty());
+        assert_eq!(removed.len(), 2);
+        assert_eq!(summary(&existing), summary(&[entry("Pool", &["a", "b"])]));
+    }
+
+    #[test]
+    fn layout_sorts_by_metadata() {
+        let set: Beatmapset = serde_json::from_value(serde_json::json!({
+            "id": 1, "artist": "Camellia", "status": "loved",
+            "beatmaps": [{ "id": 1, "checksum": "a", "difficulty_rating": 5.5 }, { "id": 2, "checksum": "b", "difficulty_rating": 3.0 }]
+        })).unwrap();
+
+        assert_eq!(Layout::Flat.subfolder(&set), None);
+        assert_eq!(Layout::ByArtist.subfolder(&set).as_deref(), Some("Camellia"));
+        assert_eq!(Layout::ByStatus.subfolder(&set).as_deref(), Some("loved"));
+        assert_eq!(Layout::BySr.subfolder(&set).as_deref(), Some("5-6 stars"));
+    }
+
+    #[test]
+    fn layout_puts_sets_without_metadata_in_unknown() {
+        let set: Beatmapset = serde_json::from_value(serde_json::json!({ "id": 1 })).unwrap();
+
+        assert_eq!(Layout::ByArtist.metadata_folder(&set), None);
+        assert_eq!(Layout::BySr.subfolder(&set).as_deref(), Some("Unknown"));
+    }
+
+    #[test]
+    fn dedupe_hashes_keep_all_changes_nothing() {
+        let entries = || vec![entry("RO16", &["a", "a", "b"]), entry("QF", &["b"])];
+
+        assert_eq!(summary(&dedupe_hashes(entries(), DedupePolicy::KeepAll)), summary(&entries()));
+    }
+
+    #[test]
+    fn dedupe_hashes_keep_first_across_entries() {
+        let deduped = dedupe_hashes(
+            vec![entry("RO16", &["a", "a", "b"]), entry("QF", &["b"]), entry("SF", &["c", "a"])],
+            DedupePolicy::KeepFirst,
+        );
+
+        // QF only reused earlier maps and is dropped
+        assert_eq!(summary(&deduped), [
+            ("RO16".to_string(), vec!["a".to_string(), "b".to_string()]),
+            ("SF".to_string(), vec!["c".to_string()]),
+        ]);
+    }
+
+    #[test]
+    fn dedupe_runs_before_splitting() {
+        let entries = vec![entry("Pool", &["a", "b", "a", "c"])];
+        let built = split_collections(dedupe_hashes(entries, DedupePolicy::KeepFirst), 2);
+
+        assert_eq!(summary(&built), [
+            ("Pool (1)".to_string(), vec!["a".to_string(), "b".to_string()]),
+            ("Pool (2)".to_string(), vec!["c".to_string()]),
+        ]);
+    }
 }
@@ -1,8 +1,15 @@
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use unicode_normalization::UnicodeNormalization;
+use crate::config::SourceConfig;
 use crate::error::{AppError, Result};
+use crate::utils::{fill_template, json_path};
 
 const MAX_RETRIES: u8 = 3;
 const COLLECTION_FETCH_TIMEOUT_SECS: u64 = 30;
+/// Beatmaps per page of the `beatmapsv2` endpoint
+const METADATA_PAGE_SIZE: u32 = 100;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Collection {
@@ -10,9 +17,33 @@ pub struct Collection {
     pub name: Box<str>,
     pub uploader: Uploader,
     pub beatmapsets: Vec<Beatmapset>,
+    /// Mappool rounds when the collection was built from a tournament
+    #[serde(skip)]
+    pub rounds: Vec<Round>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+impl Collection {
+    #[inline]
+    pub fn is_tournament(&self) -> bool {
+        !self.rounds.is_empty()
+    }
+}
+
+/// A tournament round with its slot-labeled maps, in mappool order
+#[derive(Debug)]
+pub struct Round {
+    pub name: Box<str>,
+    pub slots: Vec<Slot>,
+}
+
+#[derive(Debug)]
+pub struct Slot {
+    /// Slot label like `NM1`, `HD2` or `TB`
+    pub label: Box<str>,
+    pub checksum: Box<str>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Uploader {
     pub id: u32,
     pub username: Box<str>,
@@ -23,12 +54,536 @@ pub struct Beatmapset {
     pub id: u32,
     #[serde(default)]
     pub beatmaps: Vec<Beatmap>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist: Option<Box<str>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<Box<str>>,
+    /// When the set was added to the collection (ISO string, epoch or `{ _seconds }` timestamp)
+    #[serde(default, rename = "dateAdded", skip_serializing_if = "Option::is_none")]
+    pub date_added: Option<serde_json::Value>,
+    /// Ranked status, as a name (`ranked`, `loved`, ...) or osu! API number
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<serde_json::Value>,
+    /// Round and slot label (e.g. `RO16 NM1`) used to prefix the filename of tournament maps
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slot: Option<Box<str>>,
+    /// Folder inside the collection folder the set is saved to, see `collection::Layout`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subfolder: Option<Box<str>>,
+}
+
+impl Beatmapset {
+    /// `Artist - Title` when metadata is available
+    pub fn display_name(&self) -> Option<String> {
+        match (&self.artist, &self.title) {
+            (Some(artist), Some(title)) => Some(format!("{} - {}", artist, title)),
+            (None, Some(title)) => Some(title.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Date the set was added to the collection, as `YYYY-MM-DD`
+    pub fn date_added(&self) -> Option<String> {
+        self.date_added.as_ref().and_then(date_from_json)
+    }
+
+    /// Ranked status name (`ranked`, `approved`, `qualified`, `loved`, `pending`, `wip`, `graveyard`)
+    pub fn status_name(&self) -> Option<&'static str> {
+        const NAMES: [&str; 7] = ["ranked", "approved", "qualified", "loved", "pending", "wip", "graveyard"];
+
+        match self.status.as_ref()? {
+            serde_json::Value::String(s) => {
+                let s = s.to_ascii_lowercase();
+                NAMES.into_iter().find(|name| *name == s)
+            }
+            serde_json::Value::Number(n) => match n.as_i64()? {
+                1 => Some("ranked"),
+                2 => Some("approved"),
+                3 => Some("qualified"),
+                4 => Some("loved"),
+                0 => Some("pending"),
+                -1 => Some("wip"),
+                -2 => Some("graveyard"),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Higher is better: ranked/approved > loved > qualified > pending/wip > graveyard/unknown
+    pub fn status_rank(&self) -> u8 {
+        match self.status_name() {
+            Some("ranked" | "approved") => 4,
+            Some("loved") => 3,
+            Some("qualified") => 2,
+            Some("pending" | "wip") => 1,
+            _ => 0,
+        }
+    }
+
+    /// Highest star rating among the set's difficulties
+    pub fn max_difficulty(&self) -> Option<f64> {
+        self.beatmaps.iter()
+            .filter_map(|beatmap| beatmap.difficulty_rating)
+            .reduce(f64::max)
+    }
+
+    /// Artist and title reduced to lowercase letters and digits, so re-uploads with
+    /// different punctuation, spacing or width variants compare equal
+    fn title_key(&self) -> Option<String> {
+        let (artist, title) = (self.artist.as_deref()?, self.title.as_deref()?);
+        let key: String = format!("{}\0{}", artist, title)
+            .nfkc()
+            .filter(|c| c.is_alphanumeric() || *c == '\0')
+            .flat_map(char::to_lowercase)
+            .collect();
+        (key.len() > 1).then_some(key)
+    }
+}
+
+/// Group sets sharing artist and title under different set IDs (e.g. re-uploads).
+/// Each group is ordered best first: highest status, then collection order, and groups
+/// are in the order their first set appears. Also returns how many sets had no artist
+/// or title to compare.
+pub fn find_duplicate_titles(collection: &Collection) -> (Vec<Vec<u32>>, usize) {
+    let mut groups: Vec<Vec<&Beatmapset>> = Vec::new();
+    let mut by_key: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+    let mut untitled = 0;
+
+    for beatmapset in &collection.beatmapsets {
+        let Some(key) = beatmapset.title_key() else {
+            untitled += 1;
+            continue;
+        };
+        match by_key.get(&key) {
+            Some(&index) => groups[index].push(beatmapset),
+            None => {
+                by_key.insert(key, groups.len());
+                groups.push(vec![beatmapset]);
+            }
+        }
+    }
+
+    let groups = groups.into_iter()
+        .filter(|sets| sets.len() > 1)
+        .map(|mut sets| {
+            // Stable sort keeps collection order between equally ranked sets
+            sets.sort_by_key(|set| std::cmp::Reverse(set.status_rank()));
+            sets.into_iter().map(|set| set.id).collect()
+        })
+        .collect();
+
+    (groups, untitled)
+}
+
+/// Drop every set of each duplicate group except the best one.
+/// Returns how many sets were dropped.
+pub fn retain_best_duplicates(collection: &mut Collection, groups: &[Vec<u32>]) -> usize {
+    let dropped: std::collections::HashSet<u32> = groups.iter()
+        .flat_map(|group| group.iter().skip(1).copied())
+        .collect();
+
+    let before = collection.beatmapsets.len();
+    collection.beatmapsets.retain(|beatmapset| !dropped.contains(&beatmapset.id));
+    before - collection.beatmapsets.len()
+}
+
+/// Keep only beatmapsets added on or after `date` (`YYYY-MM-DD`).
+/// Collection order is preserved since it ends up in collection.db.
+/// Returns how many sets were dropped because they have no date-added metadata, or `None`
+/// without filtering anything when no set has one.
+pub fn retain_added_after(collection: &mut Collection, date: &str) -> Option<usize> {
+    if !collection.beatmapsets.iter().any(|beatmapset| beatmapset.date_added().is_some()) {
+        return None;
+    }
+
+    let mut undated = 0;
+
+    collection.beatmapsets.retain(|beatmapset| match beatmapset.date_added() {
+        Some(added) => added.as_str() >= date,
+        None => {
+            undated += 1;
+            false
+        }
+    });
+
+    Some(undated)
+}
+
+/// Keep only difficulties whose name contains any of `contains` (when given) and none of
+/// `exclude`, case-insensitively. Sets stay in the collection so they are still downloaded,
+/// only their hashes leave collection.db.
+/// Returns how many difficulties were dropped and how many had no name metadata, or `None`
+/// without filtering anything when no difficulty has a name.
+pub fn retain_diff_names(collection: &mut Collection, contains: &[String], exclude: &[String]) -> Option<(usize, usize)> {
+    let named = collection.beatmapsets.iter()
+        .flat_map(|set| &set.beatmaps)
+        .any(|beatmap| beatmap.version.is_some());
+    if !named {
+        return None;
+    }
+
+    let contains: Vec<String> = contains.iter().map(|s| s.to_lowercase()).collect();
+    let exclude: Vec<String> = exclude.iter().map(|s| s.to_lowercase()).collect();
+    let (mut dropped, mut unnamed) = (0, 0);
+
+    for beatmapset in &mut collection.beatmapsets {
+        beatmapset.beatmaps.retain(|beatmap| {
+            let keep = match beatmap.version.as_deref().map(str::to_lowercase) {
+                Some(name) => (contains.is_empty() || contains.iter().any(|s| name.contains(s.as_str())))
+                    && !exclude.iter().any(|s| name.contains(s.as_str())),
+                // Without a name only an include filter can rule a difficulty out
+                None => {
+                    unnamed += 1;
+                    contains.is_empty()
+                }
+            };
+            dropped += usize::from(!keep);
+            keep
+        });
+    }
+
+    let kept: std::collections::HashSet<&str> = collection.beatmapsets.iter()
+        .flat_map(|set| set.beatmaps.iter().map(|beatmap| beatmap.checksum.as_ref()))
+        .collect();
+    for round in &mut collection.rounds {
+        round.slots.retain(|slot| kept.contains(slot.checksum.as_ref()));
+    }
+
+    Some((dropped, unnamed))
+}
+
+/// A value of `--only-diffs`
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffFilter {
+    /// osu! API mode name: `osu`, `taiko`, `fruits` or `mania`
+    Mode(&'static str),
+    /// Mania key count, e.g. `4k`
+    Keys(u8),
+    /// Text the difficulty name contains, lowercase
+    Name(String),
+}
+
+impl DiffFilter {
+    fn matches(&self, beatmap: &Beatmap) -> bool {
+        match self {
+            DiffFilter::Mode(mode) => beatmap.mode.as_deref() == Some(*mode),
+            DiffFilter::Keys(keys) => {
+                beatmap.mode.as_deref() == Some("mania") && beatmap.cs == Some(f64::from(*keys))
+            }
+            DiffFilter::Name(text) => beatmap.version.as_deref()
+                .is_some_and(|name| name.to_lowercase().contains(text.as_str())),
+        }
+    }
+}
+
+/// Parse a `--only-diffs` value: a mode (`osu`, `taiko`, `catch`/`fruits`, `mania`), a mania
+/// key count like `4k`, or any other text the difficulty name has to contain
+pub fn parse_diff_filter(input: &str) -> std::result::Result<DiffFilter, String> {
+    let input = input.trim().to_lowercase();
+
+    let filter = match input.as_str() {
+        "" => return Err("empty difficulty filter".to_string()),
+        "osu" | "std" | "standard" => DiffFilter::Mode("osu"),
+        "taiko" => DiffFilter::Mode("taiko"),
+        "catch" | "fruits" | "ctb" => DiffFilter::Mode("fruits"),
+        "mania" => DiffFilter::Mode("mania"),
+        _ => match input.strip_suffix('k').and_then(|keys| keys.parse::<u8>().ok()) {
+            Some(keys @ 1..=18) => DiffFilter::Keys(keys),
+            _ => DiffFilter::Name(input),
+        },
+    };
+
+    Ok(filter)
+}
+
+/// Keep only difficulties matching any of `filters`, and only the sets that still have one, so
+/// sets without a matching difficulty aren't downloaded at all.
+/// Returns how many difficulties and sets were dropped, or `None` without filtering anything
+/// when no difficulty has the mode or name the filters read.
+pub fn retain_only_diffs(collection: &mut Collection, filters: &[DiffFilter]) -> Option<(usize, usize)> {
+    let described = collection.beatmapsets.iter()
+        .flat_map(|set| &set.beatmaps)
+        .any(|beatmap| beatmap.mode.is_some() || beatmap.version.is_some());
+    if !described {
+        return None;
+    }
+
+    let mut dropped = 0;
+    for beatmapset in &mut collection.beatmapsets {
+        let before = beatmapset.beatmaps.len();
+        beatmapset.beatmaps.retain(|beatmap| filters.iter().any(|filter| filter.matches(beatmap)));
+        dropped += before - beatmapset.beatmaps.len();
+    }
+
+    let sets = collection.beatmapsets.len();
+    collection.beatmapsets.retain(|set| !set.beatmaps.is_empty());
+
+    let kept: std::collections::HashSet<&str> = collection.beatmapsets.iter()
+        .flat_map(|set| set.beatmaps.iter().map(|beatmap| beatmap.checksum.as_ref()))
+        .collect();
+    for round in &mut collection.rounds {
+        round.slots.retain(|slot| kept.contains(slot.checksum.as_ref()));
+    }
+
+    Some((dropped, sets - collection.beatmapsets.len()))
+}
+
+/// Normalize the timestamp formats osu!collector uses into `YYYY-MM-DD`
+fn date_from_json(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(s) => {
+            let date = s.get(..10)?;
+            parse_date(date).ok().map(|_| date.to_string())
+        }
+        serde_json::Value::Number(n) => {
+            let n = n.as_i64()?;
+            // Millisecond timestamps are far beyond any plausible second timestamp
+            let secs = if n > 100_000_000_000 { n / 1000 } else { n };
+            Some(date_from_unix(secs))
+        }
+        serde_json::Value::Object(map) => map.get("_seconds")
+            .or_else(|| map.get("seconds"))
+            .and_then(date_from_json),
+        _ => None,
+    }
+}
+
+/// Validate a `YYYY-MM-DD` date
+pub fn parse_date(input: &str) -> std::result::Result<String, String> {
+    let parts: Vec<&str> = input.trim().split('-').collect();
+
+    let valid = matches!(parts.as_slice(), [y, m, d]
+        if y.len() == 4 && m.len() == 2 && d.len() == 2
+            && y.parse::<u16>().is_ok()
+            && m.parse::<u8>().is_ok_and(|m| (1..=12).contains(&m))
+            && d.parse::<u8>().is_ok_and(|d| (1..=31).contains(&d)));
+
+    if valid {
+        Ok(input.trim().to_string())
+    } else {
+        Err(format!("invalid date '{}', expected YYYY-MM-DD", input))
+    }
+}
+
+/// Convert a unix timestamp into a `YYYY-MM-DD` date (UTC)
+pub fn date_from_unix(secs: i64) -> String {
+    // Days-to-civil conversion from Howard Hinnant's date algorithms
+    let days = secs.div_euclid(86_400);
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Beatmap {
     pub id: u32,
     pub checksum: Box<str>,
+    /// Difficulty name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<Box<str>>,
+    /// Star rating
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty_rating: Option<f64>,
+    /// osu! API mode name: `osu`, `taiko`, `fruits` or `mania`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<Box<str>>,
+    /// Circle size, the key count for mania
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cs: Option<f64>,
+}
+
+/// Page of `/api/collections/{id}/beatmapsv2`
+#[derive(Debug, Deserialize)]
+struct BeatmapPage {
+    #[serde(default)]
+    beatmaps: Vec<BeatmapMetadata>,
+    #[serde(default, rename = "hasMore")]
+    has_more: bool,
+    #[serde(default, rename = "nextPageCursor")]
+    next_page_cursor: Option<serde_json::Value>,
+}
+
+/// osu! API beatmap as served by the `beatmapsv2` endpoint, reduced to what filters and layouts use
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BeatmapMetadata {
+    pub id: u32,
+    #[serde(default)]
+    pub checksum: Option<Box<str>>,
+    /// Difficulty name
+    #[serde(default)]
+    pub version: Option<Box<str>>,
+    #[serde(default)]
+    pub difficulty_rating: Option<f64>,
+    #[serde(default)]
+    pub mode: Option<Box<str>>,
+    #[serde(default)]
+    pub cs: Option<f64>,
+    #[serde(default)]
+    pub beatmapset: Option<BeatmapsetMetadata>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BeatmapsetMetadata {
+    pub id: u32,
+    #[serde(default)]
+    pub artist: Option<Box<str>>,
+    #[serde(default)]
+    pub title: Option<Box<str>>,
+    #[serde(default)]
+    pub status: Option<serde_json::Value>,
+}
+
+impl Collection {
+    /// Fill in metadata fetched with `fetch_collection_metadata`, matched by beatmap ID.
+    /// Values the collection already carries are kept. Returns how many beatmaps got metadata.
+    pub fn apply_metadata(&mut self, metadata: &[BeatmapMetadata]) -> usize {
+        let by_id: std::collections::HashMap<u32, &BeatmapMetadata> =
+            metadata.iter().map(|beatmap| (beatmap.id, beatmap)).collect();
+        let mut matched = 0;
+
+        for beatmapset in &mut self.beatmapsets {
+            for beatmap in &mut beatmapset.beatmaps {
+                let Some(found) = by_id.get(&beatmap.id) else { continue };
+                matched += 1;
+
+                if beatmap.version.is_none() {
+                    beatmap.version = found.version.clone();
+                }
+                if beatmap.difficulty_rating.is_none() {
+                    beatmap.difficulty_rating = found.difficulty_rating;
+                }
+                if beatmap.mode.is_none() {
+                    beatmap.mode = found.mode.clone();
+                }
+                if beatmap.cs.is_none() {
+                    beatmap.cs = found.cs;
+                }
+
+                if let Some(set) = &found.beatmapset {
+                    if beatmapset.artist.is_none() {
+                        beatmapset.artist = set.artist.clone();
+                    }
+                    if beatmapset.title.is_none() {
+                        beatmapset.title = set.title.clone();
+                    }
+                    if beatmapset.status.is_none() {
+                        beatmapset.status = set.status.clone();
+                    }
+                }
+            }
+        }
+
+        matched
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Tournament {
+    pub id: u32,
+    pub name: Box<str>,
+    #[serde(default)]
+    pub uploader: Option<Uploader>,
+    #[serde(default)]
+    pub rounds: Vec<TournamentRound>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TournamentRound {
+    pub round: Box<str>,
+    #[serde(default)]
+    pub mods: Vec<TournamentMod>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TournamentMod {
+    #[serde(rename = "mod")]
+    pub mod_name: Box<str>,
+    #[serde(default)]
+    pub maps: Vec<TournamentMap>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TournamentMap {
+    pub id: u32,
+    pub checksum: Box<str>,
+    pub beatmapset: TournamentBeatmapset,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TournamentBeatmapset {
+    pub id: u32,
+    #[serde(default)]
+    pub artist: Option<Box<str>>,
+    #[serde(default)]
+    pub title: Option<Box<str>>,
+}
+
+impl Tournament {
+    /// Flatten the mappool into a collection, keeping every round's slot labels.
+    /// A set used in several rounds is downloaded once and labeled by its first slot.
+    pub fn into_collection(self) -> Collection {
+        let mut beatmapsets: Vec<Beatmapset> = Vec::new();
+        let mut rounds = Vec::with_capacity(self.rounds.len());
+
+        for round in self.rounds {
+            let mut slots = Vec::new();
+
+            for group in round.mods {
+                let single = group.maps.len() == 1;
+
+                for (index, map) in group.maps.into_iter().enumerate() {
+                    let label: Box<str> = if single {
+                        group.mod_name.clone()
+                    } else {
+                        format!("{}{}", group.mod_name, index + 1).into_boxed_str()
+                    };
+
+                    let beatmap = Beatmap { id: map.id, checksum: map.checksum.clone(), version: None, difficulty_rating: None, mode: None, cs: None };
+                    match beatmapsets.iter_mut().find(|set| set.id == map.beatmapset.id) {
+                        Some(set) => {
+                            if !set.beatmaps.iter().any(|b| b.id == beatmap.id) {
+                                set.beatmaps.push(beatmap);
+                            }
+                        }
+                        None => beatmapsets.push(Beatmapset {
+                            id: map.beatmapset.id,
+                            beatmaps: vec![beatmap],
+                            artist: map.beatmapset.artist,
+                            title: map.beatmapset.title,
+                            date_added: None,
+                            status: None,
+                            slot: Some(format!("{} {}", round.round, label).into_boxed_str()),
+                            subfolder: None,
+                        }),
+                    }
+
+                    slots.push(Slot {
+                        label,
+                        checksum: map.checksum,
+                    });
+                }
+            }
+
+            rounds.push(Round { name: round.round, slots });
+        }
+
+        Collection {
+            id: self.id,
+            name: self.name,
+            uploader: self.uploader.unwrap_or_default(),
+            beatmapsets,
+            rounds,
+        }
+    }
 }
 
 /// Fetch collection from osucollector API with retry logic
@@ -37,11 +592,157 @@ pub async fn fetch_collection(
     collection_id: u32,
 ) -> Result<Collection> {
     let url = format!("https://osucollector.com/api/collections/{}", collection_id);
+    fetch_with_retry(client, &url, &BTreeMap::new(), &format!("Collection {}", collection_id)).await
+}
+
+/// Fetch the difficulty names, star ratings, artist, title and status of a collection's beatmaps.
+/// `/api/collections/{id}` only lists IDs and checksums, osu!collector serves the osu! API
+/// beatmaps with their beatmapset from the paginated `beatmapsv2` endpoint.
+pub async fn fetch_collection_metadata(
+    client: &reqwest::Client,
+    collection_id: u32,
+) -> Result<Vec<BeatmapMetadata>> {
+    let mut beatmaps = Vec::new();
+    let mut cursor: Option<String> = None;
+
+    loop {
+        let mut url = format!(
+            "https://osucollector.com/api/collections/{}/beatmapsv2?perPage={}",
+            collection_id, METADATA_PAGE_SIZE
+        );
+        if let Some(cursor) = &cursor {
+            url.push_str(&format!("&cursor={}", cursor));
+        }
+
+        let page: BeatmapPage =
+            fetch_with_retry(client, &url, &BTreeMap::new(), &format!("Collection {} beatmaps", collection_id)).await?;
+        let fetched = page.beatmaps.len();
+        beatmaps.extend(page.beatmaps);
+
+        let next = match page.next_page_cursor {
+            Some(serde_json::Value::Number(n)) => Some(n.to_string()),
+            Some(serde_json::Value::String(s)) => Some(s),
+            _ => None,
+        };
+        // A repeated cursor or an empty page would loop forever
+        match next {
+            Some(next) if page.has_more && fetched > 0 && cursor.as_ref() != Some(&next) => cursor = Some(next),
+            _ => break,
+        }
+    }
+
+    Ok(beatmaps)
+}
+
+/// Fetch tournament mappool from osucollector API with retry logic
+pub async fn fetch_tournament(
+    client: &reqwest::Client,
+    tournament_id: u32,
+) -> Result<Collection> {
+    let url = format!("https://osucollector.com/api/tournaments/{}", tournament_id);
+    let tournament: Tournament =
+        fetch_with_retry(client, &url, &BTreeMap::new(), &format!("Tournament {}", tournament_id)).await?;
+
+    Ok(tournament.into_collection())
+}
+
+/// Fetch a list from a config-defined source and map it onto a collection
+pub async fn fetch_custom(
+    client: &reqwest::Client,
+    source: &SourceConfig,
+    id: u32,
+) -> Result<Collection> {
+    let vars: [(Box<str>, Box<str>); 1] = [("id".into(), id.to_string().into_boxed_str())];
+    let url = fill_template(&source.url, &vars);
+    let headers: BTreeMap<Box<str>, Box<str>> = source.headers.iter()
+        .map(|(name, value)| (name.clone(), fill_template(value, &vars).into_boxed_str()))
+        .collect();
+
+    let value: serde_json::Value =
+        fetch_with_retry(client, &url, &headers, &format!("{} list {}", source.name, id)).await?;
+
+    collection_from_json(source, id, &value)
+}
+
+/// Map a custom source response onto a collection using the configured paths
+fn collection_from_json(source: &SourceConfig, id: u32, value: &serde_json::Value) -> Result<Collection> {
+    let invalid = |what: &str| AppError::api_dynamic(
+        format!("Source '{}': {}", source.name, what).into_boxed_str()
+    );
+    let string_at = |item: &serde_json::Value, path: Option<&str>| -> Option<Box<str>> {
+        json_path(item, path?)?.as_str().map(Into::into)
+    };
+    let id_at = |item: &serde_json::Value, path: &str| -> Option<u32> {
+        match json_path(item, path)? {
+            serde_json::Value::Number(n) => n.as_u64().and_then(|n| u32::try_from(n).ok()),
+            serde_json::Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    };
+
+    let items = json_path(value, &source.beatmapsets)
+        .and_then(|sets| sets.as_array())
+        .ok_or_else(|| invalid(&format!("'{}' is not an array", source.beatmapsets)))?;
+
+    let mut beatmapsets = Vec::with_capacity(items.len());
+    for item in items {
+        let set_id = id_at(item, &source.set_id)
+            .ok_or_else(|| invalid(&format!("beatmapset without a numeric '{}'", source.set_id)))?;
+
+        let beatmaps = source.checksums.as_deref()
+            .and_then(|path| json_path(item, path))
+            .and_then(|beatmaps| beatmaps.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|beatmap| {
+                let checksum = match source.checksum.as_deref() {
+                    Some(path) => json_path(beatmap, path)?,
+                    None => beatmap,
+                };
+                Some(Beatmap {
+                    id: source.beatmap_id.as_deref().and_then(|path| id_at(beatmap, path)).unwrap_or(0),
+                    checksum: checksum.as_str()?.into(),
+                    version: None,
+                    difficulty_rating: None,
+                    mode: None,
+                    cs: None,
+                })
+            })
+            .collect();
+
+        beatmapsets.push(Beatmapset {
+            id: set_id,
+            beatmaps,
+            artist: string_at(item, source.artist.as_deref()),
+            title: string_at(item, source.title.as_deref()),
+            date_added: None,
+            status: None,
+            slot: None,
+            subfolder: None,
+        });
+    }
+
+    Ok(Collection {
+        id,
+        name: string_at(value, source.collection_name.as_deref())
+            .unwrap_or_else(|| format!("{} {}", source.name, id).into_boxed_str()),
+        uploader: Uploader::default(),
+        beatmapsets,
+        rounds: Vec::new(),
+    })
+}
+
+async fn fetch_with_retry<T: DeserializeOwned>(
+    client: &reqwest::Client,
+    url: &str,
+    headers: &BTreeMap<Box<str>, Box<str>>,
+    what: &str,
+) -> Result<T> {
     let mut last_error = None;
 
     for attempt in 1..=MAX_RETRIES {
-        match try_fetch_collection(client, &url, collection_id).await {
-            Ok(collection) => return Ok(collection),
+        match try_fetch(client, url, headers, what).await {
+            Ok(value) => return Ok(value),
             Err(e) => {
                 let should_retry = matches!(e, AppError::Network(_));
 
@@ -71,18 +772,27 @@ pub fn create_collection_client() -> Result<reqwest::Client> {
         .map_err(AppError::Network)
 }
 
-/// Single attempt to fetch collection
-async fn try_fetch_collection(
+/// Single attempt to fetch a collection or tournament
+async fn try_fetch<T: DeserializeOwned>(
     client: &reqwest::Client,
     url: &str,
-    collection_id: u32,
-) -> Result<Collection> {
-    let response = client.get(url).send().await
+    headers: &BTreeMap<Box<str>, Box<str>>,
+    what: &str,
+) -> Result<T> {
+    let host = reqwest::Url::parse(url).ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default();
+
+    let request = headers.iter().fold(client.get(url), |request, (name, value)| {
+        request.header(name.as_ref(), value.as_ref())
+    });
+
+    let response = request.send().await
         .map_err(|e| {
             if e.is_timeout() {
                 AppError::api("Request timed out after 30 seconds")
             } else if e.is_connect() {
-                AppError::api("Failed to connect to osucollector.com")
+                AppError::api_dynamic(format!("Failed to connect to {}", host).into_boxed_str())
             } else {
                 AppError::from(e)
             }
@@ -92,33 +802,251 @@ async fn try_fetch_collection(
 
     if status == reqwest::StatusCode::NOT_FOUND {
         return Err(AppError::api_dynamic(
-            format!("Collection {} not found (404)", collection_id).into_boxed_str()
+            format!("{} not found (404)", what).into_boxed_str()
         ));
     }
 
     if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
-        return Err(AppError::api(
-            "Rate limited by osucollector.com (429). Please try again later."
+        return Err(AppError::api_dynamic(
+            format!("Rate limited by {} (429). Please try again later.", host).into_boxed_str()
         ));
     }
 
     if !status.is_success() {
         return Err(AppError::api_dynamic(
-            format!("Failed to fetch collection: HTTP {}", status).into_boxed_str()
+            format!("Failed to fetch {}: HTTP {}", what.to_lowercase(), status).into_boxed_str()
         ));
     }
 
-    let collection: Collection = response.json().await
+    response.json().await
         .map_err(|e| AppError::api_dynamic(
-            format!("Failed to parse collection JSON: {}", e).into_boxed_str()
-        ))?;
-
-    Ok(collection)
+            format!("Failed to parse {} JSON: {}", what.to_lowercase(), e).into_boxed_str()
+        ))
 }
 
 /// Display collection information
 pub fn display_collection_info(collection: &Collection) {
-    println!("\nCollection: \"{}\"", collection.name);
-    println!("Uploader: {}", collection.uploader.username);
+    if collection.is_tournament() {
+        println!("\nTournament: \"{}\"", collection.name);
+        println!("Rounds: {}", collection.rounds.len());
+        for round in &collection.rounds {
+            let labels: Vec<&str> = round.slots.iter().map(|slot| slot.label.as_ref()).collect();
+            println!("  {}: {}", round.name, labels.join(" "));
+        }
+    } else {
+        println!("\nCollection: \"{}\"", collection.name);
+    }
+    if !collection.uploader.username.is_empty() {
+        println!("Uploader: {}", collection.uploader.username);
+    }
     println!("Total beatmaps: {}", collection.beatmapsets.len());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Trimmed `beatmapsv2` page in the shape osu!collector serves (osu! API beatmaps with
+    /// their beatmapset), extra fields included to check they're ignored
+    const BEATMAPS_PAGE: &str = r#"{
+        "hasMore": true,
+        "nextPageCursor": 1234,
+        "beatmaps": [
+            {
+                "id": 1001, "beatmapset_id": 10, "checksum": "aaa", "mode": "osu", "status": "ranked",
+                "difficulty_rating": 5.67, "version": "Insane", "bpm": 180, "cs": 4,
+                "beatmapset": { "id": 10, "artist": "Camellia", "title": "Exit This Earth's Atomosphere", "status": "ranked", "creator": "someone" }
+            },
+            {
+                "id": 1002, "beatmapset_id": 10, "checksum": "bbb", "mode": "mania",
+                "difficulty_rating": 3.1, "version": "4K Normal", "cs": 4,
+                "beatmapset": { "id": 10, "artist": "Camellia", "title": "Exit This Earth's Atomosphere", "status": "ranked" }
+            },
+            { "id": 2001, "checksum": "ccc" }
+        ]
+    }"#;
+
+    fn collection(sets: Vec<Beatmapset>) -> Collection {
+        Collection {
+            id: 1,
+            name: "Test".into(),
+            uploader: Uploader::default(),
+            beatmapsets: sets,
+            rounds: Vec::new(),
+        }
+    }
+
+    fn beatmapset(id: u32, beatmap_ids: &[u32]) -> Beatmapset {
+        Beatmapset {
+            id,
+            beatmaps: beatmap_ids.iter()
+                .map(|&id| Beatmap { id, checksum: format!("{}", id).into(), version: None, difficulty_rating: None, mode: None, cs: None })
+                .collect(),
+            artist: None,
+            title: None,
+            date_added: None,
+            status: None,
+            slot: None,
+            subfolder: None,
+        }
+    }
+
+    #[test]
+    fn parses_beatmaps_page() {
+        let page: BeatmapPage = serde_json::from_str(BEATMAPS_PAGE).unwrap();
+
+        assert!(page.has_more);
+        assert_eq!(page.next_page_cursor, Some(serde_json::json!(1234)));
+        assert_eq!(page.beatmaps.len(), 3);
+        assert_eq!(page.beatmaps[0].version.as_deref(), Some("Insane"));
+        assert!(page.beatmaps[2].beatmapset.is_none());
+    }
+
+    #[test]
+    fn apply_metadata_fills_sets_and_difficulties() {
+        let page: BeatmapPage = serde_json::from_str(BEATMAPS_PAGE).unwrap();
+        let mut collection = collection(vec![beatmapset(10, &[1001, 1002]), beatmapset(20, &[2001, 2002])]);
+
+        assert_eq!(collection.apply_metadata(&page.beatmaps), 3);
+
+        let set = &collection.beatmapsets[0];
+        assert_eq!(set.display_name().as_deref(), Some("Camellia - Exit This Earth's Atomosphere"));
+        assert_eq!(set.status_name(), Some("ranked"));
+        assert_eq!(set.max_difficulty(), Some(5.67));
+        assert_eq!(set.beatmaps[1].version.as_deref(), Some("4K Normal"));
+
+        // Matched without a beatmapset, so only the difficulty is known
+        let set = &collection.beatmapsets[1];
+        assert_eq!(set.display_name(), None);
+        assert_eq!(set.beatmaps[0].version, None);
+    }
+
+    #[test]
+    fn retain_diff_names_filters_named_difficulties() {
+        let page: BeatmapPage = serde_json::from_str(BEATMAPS_PAGE).unwrap();
+        let mut collection = collection(vec![beatmapset(10, &[1001, 1002]), beatmapset(20, &[2001])]);
+        collection.apply_metadata(&page.beatmaps);
+
+        let contains = ["insane".to_string()];
+        assert_eq!(retain_diff_names(&mut collection, &contains, &[]), Some((2, 1)));
+        assert_eq!(collection.beatmapsets[0].beatmaps.iter().map(|b| b.id).collect::<Vec<_>>(), [1001]);
+        assert!(collection.beatmapsets[1].beatmaps.is_empty());
+    }
+
+    #[test]
+    fn retain_diff_names_counts_unnamed_with_exclude_filters() {
+        let page: BeatmapPage = serde_json::from_str(BEATMAPS_PAGE).unwrap();
+        let mut collection = collection(vec![beatmapset(10, &[1001, 1002]), beatmapset(20, &[2001])]);
+        collection.apply_metadata(&page.beatmaps);
+
+        assert_eq!(retain_diff_names(&mut collection, &[], &["4k".to_string()]), Some((1, 1)));
+        assert_eq!(collection.beatmapsets[1].beatmaps.len(), 1);
+    }
+
+    #[test]
+    fn retain_diff_names_needs_names() {
+        let mut collection = collection(vec![beatmapset(10, &[1, 2])]);
+
+        assert_eq!(retain_diff_names(&mut collection, &["insane".to_string()], &[]), None);
+        assert_eq!(collection.beatmapsets[0].beatmaps.len(), 2);
+    }
+
+    #[test]
+    fn parse_diff_filter_reads_modes_and_keys() {
+        assert_eq!(parse_diff_filter("Mania"), Ok(DiffFilter::Mode("mania")));
+        assert_eq!(parse_diff_filter("ctb"), Ok(DiffFilter::Mode("fruits")));
+        assert_eq!(parse_diff_filter("7K"), Ok(DiffFilter::Keys(7)));
+        assert_eq!(parse_diff_filter("Insane"), Ok(DiffFilter::Name("insane".to_string())));
+        assert_eq!(parse_diff_filter("0k"), Ok(DiffFilter::Name("0k".to_string())));
+        assert!(parse_diff_filter(" ").is_err());
+    }
+
+    #[test]
+    fn retain_only_diffs_skips_sets_without_a_match() {
+        let page: BeatmapPage = serde_json::from_str(BEATMAPS_PAGE).unwrap();
+        let mut collection = collection(vec![beatmapset(10, &[1001, 1002]), beatmapset(20, &[2001])]);
+        collection.apply_metadata(&page.beatmaps);
+
+        assert_eq!(retain_only_diffs(&mut collection, &[DiffFilter::Keys(4)]), Some((2, 1)));
+        assert_eq!(collection.beatmapsets.len(), 1);
+        assert_eq!(collection.beatmapsets[0].beatmaps.iter().map(|b| b.id).collect::<Vec<_>>(), [1002]);
+    }
+
+    #[test]
+    fn retain_only_diffs_needs_metadata() {
+        let mut collection = collection(vec![beatmapset(10, &[1, 2])]);
+
+        assert_eq!(retain_only_diffs(&mut collection, &[DiffFilter::Mode("mania")]), None);
+        assert_eq!(collection.beatmapsets.len(), 1);
+    }
+
+    #[test]
+    fn find_duplicate_titles_groups_reuploads() {
+        let titled = |id, artist: &str, title: &str, status: &str| Beatmapset {
+            artist: Some(artist.into()),
+            title: Some(title.into()),
+            status: Some(serde_json::json!(status)),
+            ..beatmapset(id, &[id])
+        };
+        let collection = collection(vec![
+            titled(1, "Camellia", "Ghost", "graveyard"),
+            titled(2, "xi", "Blue Zenith", "ranked"),
+            beatmapset(3, &[3]),
+            titled(4, "camellia", "GHOST!", "ranked"),
+            titled(5, "Ｃａｍｅｌｌｉａ", "Ghost", "loved"),
+        ]);
+
+        let (groups, untitled) = find_duplicate_titles(&collection);
+
+        assert_eq!(groups, [vec![4, 5, 1]]);
+        assert_eq!(untitled, 1);
+    }
+
+    #[test]
+    fn retain_best_duplicates_keeps_first_of_each_group() {
+        let mut collection = collection(vec![beatmapset(1, &[1]), beatmapset(2, &[2]), beatmapset(3, &[3])]);
+
+        assert_eq!(retain_best_duplicates(&mut collection, &[vec![3, 1]]), 1);
+        assert_eq!(collection.beatmapsets.iter().map(|set| set.id).collect::<Vec<_>>(), [2, 3]);
+    }
+
+    #[test]
+    fn retain_added_after_keeps_everything_without_dates() {
+        let mut collection = collection(vec![beatmapset(10, &[1]), beatmapset(20, &[2])]);
+
+        assert_eq!(retain_added_after(&mut collection, "2024-01-01"), None);
+        assert_eq!(collection.beatmapsets.len(), 2);
+
+        collection.beatmapsets[0].date_added = Some(serde_json::json!("2024-03-01T00:00:00Z"));
+        assert_eq!(retain_added_after(&mut collection, "2024-01-01"), Some(1));
+        assert_eq!(collection.beatmapsets.iter().map(|set| set.id).collect::<Vec<_>>(), [10]);
+    }
+
+    #[test]
+    fn date_from_unix_converts_known_dates() {
+        assert_eq!(date_from_unix(0), "1970-01-01");
+        assert_eq!(date_from_unix(86_399), "1970-01-01");
+        assert_eq!(date_from_unix(951_782_400), "2000-02-29");
+        assert_eq!(date_from_unix(1_709_251_199), "2024-02-29");
+        assert_eq!(date_from_unix(-1), "1969-12-31");
+    }
+
+    #[test]
+    fn date_from_json_accepts_osu_collector_formats() {
+        let date = |value| date_from_json(&value);
+
+        assert_eq!(date(serde_json::json!("2024-02-29T12:00:00.000Z")), Some("2024-02-29".into()));
+        assert_eq!(date(serde_json::json!(1_709_251_199)), Some("2024-02-29".into()));
+        assert_eq!(date(serde_json::json!(1_709_251_199_000i64)), Some("2024-02-29".into()));
+        assert_eq!(date(serde_json::json!({ "_seconds": 0 })), Some("1970-01-01".into()));
+        assert_eq!(date(serde_json::json!("yesterday")), None);
+    }
+
+    #[test]
+    fn parse_date_validates_format() {
+        assert_eq!(parse_date(" 2024-01-31 "), Ok("2024-01-31".into()));
+        assert!(parse_date("2024-13-01").is_err());
+        assert!(parse_date("2024-1-01").is_err());
+        assert!(parse_date("24-01-01").is_err());
+    }
+}
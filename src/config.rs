@@ -1,33 +1,294 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use crate::error::{AppError, Result};
+use crate::options::{DedupePolicy, DownloadOrder, FileExistsAction, HashAlgorithm, SinkKind};
+use crate::utils::{checksum, normalize_url_host, write_atomic};
+use std::path::{Path, PathBuf};
+
+pub const CONFIG_VERSION: u32 = 1;
+/// First line of configs written by osu-collect, followed by the checksum of the rest of the file
+const CHECKSUM_PREFIX: &str = "# osu-collect checksum ";
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Config {
+    #[serde(default)]
+    pub version: u32,
     pub mirror: MirrorConfig,
     pub download: DownloadConfig,
+    #[serde(default)]
+    pub notifications: NotificationConfig,
+    #[serde(default)]
+    pub collection_db: CollectionDbConfig,
+    #[serde(default)]
+    pub manifest: ManifestConfig,
+    #[serde(default)]
+    pub backups: BackupConfig,
+    #[serde(default)]
+    pub output: OutputConfig,
+    /// Custom collection sources, used as `-c <name>:<id>`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<SourceConfig>,
+}
+
+/// JSON endpoint mapped onto a collection with dotted paths (see `utils::json_path`)
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SourceConfig {
+    pub name: Box<str>,
+    /// URL template, `{id}` is replaced with the requested ID
+    pub url: Box<str>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<Box<str>, Box<str>>,
+    /// Path of the collection name in the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collection_name: Option<Box<str>>,
+    /// Path of the beatmapset array in the response (empty for the root)
+    #[serde(default)]
+    pub beatmapsets: Box<str>,
+    /// Path of the set ID within each beatmapset
+    #[serde(default = "default_id_path")]
+    pub set_id: Box<str>,
+    /// Path of the beatmap array within each beatmapset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksums: Option<Box<str>>,
+    /// Path of the MD5 within each beatmap, unset when beatmaps are plain strings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<Box<str>>,
+    /// Path of the beatmap ID within each beatmap
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beatmap_id: Option<Box<str>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist: Option<Box<str>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<Box<str>>,
+}
+
+fn default_id_path() -> Box<str> {
+    "id".into()
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct CollectionDbConfig {
+    /// Split collections with more hashes than this into numbered parts (0 disables)
+    #[serde(default)]
+    pub split_at: usize,
+    /// Handling of beatmap hashes repeated across entries
+    #[serde(default)]
+    pub dedupe: DedupePolicy,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ManifestConfig {
+    /// Hash recorded for downloaded files, computed while they are streamed
+    #[serde(default)]
+    pub hash: HashAlgorithm,
+}
+
+/// Where downloaded files are put, see `sink::OutputSink`
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct OutputConfig {
+    #[serde(default)]
+    pub sink: SinkKind,
+    /// Base URL of the WebDAV sink
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<Box<str>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<Box<str>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<Box<str>>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
+pub struct BackupConfig {
+    /// Backups kept of each file written in an osu! install (0 disables)
+    #[serde(default = "default_backups_keep")]
+    pub keep: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            keep: default_backups_keep(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct MirrorConfig {
     pub url: Box<str>,
+    /// Mirror URL templates tried in order when the main mirror fails
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallbacks: Vec<Box<str>>,
+    /// User-supplied archive URL templates tried as a last resort for sets every mirror
+    /// reported missing, none are built in
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub archives: Vec<Box<str>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<Box<str>, Box<str>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<MirrorStep>,
+}
+
+/// Intermediate request run before the download (e.g. fetching a token)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MirrorStep {
+    #[serde(default = "default_step_method")]
+    pub method: Box<str>,
+    pub url: Box<str>,
+    #[serde(default)]
+    pub body: Option<Box<str>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<Box<str>, Box<str>>,
+    /// Values to capture from the JSON response, as `name = "dotted.path"`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extract: BTreeMap<Box<str>, Box<str>>,
+}
+
+fn default_step_method() -> Box<str> {
+    "GET".into()
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct DownloadConfig {
     pub skip_existing: bool,
     pub concurrent: u8,
+    /// Collections from a list downloaded at the same time, sharing the `concurrent` slots
+    #[serde(default = "default_parallel_collections")]
+    pub parallel_collections: u8,
+    /// Action for existing files when stdin is not a terminal and prompts can't be answered
+    #[serde(default)]
+    pub file_exists_default: FileExistsAction,
+    /// Extension downloaded archives are saved with (without the dot)
+    #[serde(default = "default_extension")]
+    pub extension: Box<str>,
+    /// Order beatmapsets are downloaded in
+    #[serde(default)]
+    pub order: DownloadOrder,
+    /// Seconds a single file may take before it's cancelled and tried on the next mirror (0 disables)
+    #[serde(default)]
+    pub per_file_deadline_secs: u64,
+    /// Space out requests to the preset mirrors (see `mirror::RateLimits`), on by default
+    #[serde(default = "default_mirror_limits")]
+    pub mirror_limits: bool,
+    /// Completed downloads between state and manifest checkpoints during a run (0 only saves at the end)
+    #[serde(default = "default_checkpoint_interval")]
+    pub checkpoint_interval: u32,
+    /// Store set ID, collection and source URL as xattrs / NTFS streams on downloaded files
+    #[serde(default)]
+    pub metadata_attributes: bool,
+    /// Octal permissions for downloaded files on Unix, e.g. "0644"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_mode: Option<Box<str>>,
+    /// `uid:gid` to chown downloaded files to on Unix (requires root)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Box<str>>,
+}
+
+impl DownloadConfig {
+    /// Parsed `file_mode` permission bits
+    pub fn file_mode(&self) -> Result<Option<u32>> {
+        self.file_mode.as_deref()
+            .map(|mode| {
+                u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+                    .ok()
+                    .filter(|mode| *mode <= 0o7777)
+                    .ok_or_else(|| AppError::other_dynamic(
+                        format!("Invalid file_mode '{}', expected octal like \"0644\"", mode).into_boxed_str()
+                    ))
+            })
+            .transpose()
+    }
+
+    /// `per_file_deadline_secs` as a duration, `None` when disabled
+    pub fn deadline(&self) -> Option<std::time::Duration> {
+        (self.per_file_deadline_secs > 0).then(|| std::time::Duration::from_secs(self.per_file_deadline_secs))
+    }
+
+    /// Parsed `owner` as `(uid, gid)`
+    pub fn owner(&self) -> Result<Option<(u32, u32)>> {
+        self.owner.as_deref()
+            .map(|owner| {
+                owner.split_once(':')
+                    .and_then(|(uid, gid)| Some((uid.parse().ok()?, gid.parse().ok()?)))
+                    .ok_or_else(|| AppError::other_dynamic(
+                        format!("Invalid owner '{}', expected numeric \"uid:gid\"", owner).into_boxed_str()
+                    ))
+            })
+            .transpose()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct NotificationConfig {
+    pub enabled: bool,
+    /// Consecutive failures before alerting that the mirror is likely down (0 disables)
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        NotificationConfig {
+            enabled: false,
+            failure_threshold: default_failure_threshold(),
+        }
+    }
+}
+
+fn default_extension() -> Box<str> {
+    "osz".into()
+}
+
+fn default_parallel_collections() -> u8 {
+    1
+}
+
+fn default_mirror_limits() -> bool {
+    true
+}
+
+fn default_checkpoint_interval() -> u32 {
+    50
+}
+
+fn default_backups_keep() -> usize {
+    5
+}
+
+fn default_failure_threshold() -> u32 {
+    10
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
+            version: CONFIG_VERSION,
             mirror: MirrorConfig {
                 url: "https://api.nerinyan.moe/d/{id}".into(),
+                fallbacks: Vec::new(),
+                archives: Vec::new(),
+                headers: BTreeMap::new(),
+                steps: Vec::new(),
             },
             download: DownloadConfig {
                 skip_existing: false,
                 concurrent: 3,
+                parallel_collections: default_parallel_collections(),
+                file_exists_default: FileExistsAction::default(),
+                extension: default_extension(),
+                order: DownloadOrder::default(),
+                per_file_deadline_secs: 0,
+                mirror_limits: default_mirror_limits(),
+                checkpoint_interval: default_checkpoint_interval(),
+                metadata_attributes: false,
+                file_mode: None,
+                owner: None,
             },
+            notifications: NotificationConfig::default(),
+            collection_db: CollectionDbConfig::default(),
+            manifest: ManifestConfig::default(),
+            backups: BackupConfig::default(),
+            output: OutputConfig::default(),
+            sources: Vec::new(),
         }
     }
 }
@@ -35,24 +296,120 @@ impl Default for Config {
 impl Config {
     /// Validate configuration
     pub fn validate(&self) -> Result<()> {
-        if !self.mirror.url.contains("{id}") {
+        let id_in_steps = self.mirror.steps.iter().any(|step| {
+            step.url.contains("{id}") || step.body.as_deref().is_some_and(|b| b.contains("{id}"))
+        });
+
+        if !self.mirror.url.contains("{id}") && !id_in_steps {
             return Err(AppError::other(
                 "Mirror URL must contain {id} placeholder"
             ));
         }
 
-        if !self.mirror.url.starts_with("http://") && !self.mirror.url.starts_with("https://") {
+        if !is_http_url(&self.mirror.url) {
             return Err(AppError::other(
                 "Mirror URL must start with http:// or https://"
             ));
         }
 
+        for fallback in &self.mirror.fallbacks {
+            if !fallback.contains("{id}") {
+                return Err(AppError::other(
+                    "Fallback mirror URL must contain {id} placeholder"
+                ));
+            }
+
+            if !is_http_url(fallback) {
+                return Err(AppError::other(
+                    "Fallback mirror URL must start with http:// or https://"
+                ));
+            }
+        }
+
+        for archive in &self.mirror.archives {
+            if !archive.contains("{id}") || !is_http_url(archive) {
+                return Err(AppError::other(
+                    "Archive URL must start with http:// or https:// and contain {id} placeholder"
+                ));
+            }
+        }
+
+        for step in &self.mirror.steps {
+            if !is_http_url(&step.url) {
+                return Err(AppError::other(
+                    "Mirror step URL must start with http:// or https://"
+                ));
+            }
+
+            crate::mirror::parse_method(&step.method)?;
+        }
+
+        let templates = std::iter::once(&self.mirror.url)
+            .chain(&self.mirror.fallbacks)
+            .chain(&self.mirror.archives)
+            .chain(self.mirror.steps.iter().map(|step| &step.url));
+        for template in templates {
+            if normalize_url_host(template).is_none() {
+                return Err(AppError::other_dynamic(
+                    format!("Invalid host in mirror URL '{}'", template).into_boxed_str()
+                ));
+            }
+        }
+
         if self.download.concurrent == 0 {
             return Err(AppError::other(
                 "Concurrent downloads must be at least 1"
             ));
         }
 
+        if self.download.parallel_collections == 0 {
+            return Err(AppError::other(
+                "Parallel collections must be at least 1"
+            ));
+        }
+
+        if self.download.extension.is_empty()
+            || !self.download.extension.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return Err(AppError::other(
+                "Download extension must be alphanumeric without the dot, e.g. \"osz\""
+            ));
+        }
+
+        if self.output.sink == SinkKind::WebDav
+            && !self.output.url.as_deref().is_some_and(is_http_url)
+        {
+            return Err(AppError::other(
+                "output.url must start with http:// or https:// for the webdav sink"
+            ));
+        }
+
+        self.download.file_mode()?;
+        self.download.owner()?;
+
+        for (index, source) in self.sources.iter().enumerate() {
+            if source.name.is_empty()
+                || !source.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                || matches!(source.name.as_ref(), "http" | "https")
+            {
+                return Err(AppError::other_dynamic(
+                    format!("Invalid source name '{}', use letters, digits, '-' and '_'", source.name).into_boxed_str()
+                ));
+            }
+
+            if self.sources[..index].iter().any(|other| other.name == source.name) {
+                return Err(AppError::other_dynamic(
+                    format!("Source '{}' is defined more than once", source.name).into_boxed_str()
+                ));
+            }
+
+            if !source.url.contains("{id}") || !is_http_url(&source.url) {
+                return Err(AppError::other_dynamic(
+                    format!("Source '{}' URL must start with http:// or https:// and contain {{id}}", source.name).into_boxed_str()
+                ));
+            }
+        }
+
         if self.download.concurrent > 50 {
             eprintln!("Warning: concurrent downloads set to {}, which is unusually high.",
                       self.download.concurrent);
@@ -62,11 +419,51 @@ impl Config {
         Ok(())
     }
 
+    /// Main mirror followed by the fallback mirrors, in the order they are tried
+    pub fn mirrors(&self) -> Vec<MirrorConfig> {
+        let mut main = self.mirror.clone();
+        main.url = ascii_host(&main.url);
+        for step in &mut main.steps {
+            step.url = ascii_host(&step.url);
+        }
+
+        let fallbacks = self.mirror.fallbacks.iter().map(|url| plain_mirror(&ascii_host(url)));
+
+        std::iter::once(main).chain(fallbacks).collect()
+    }
+
+    /// Make `url` the main mirror, the previous main one and the other fallbacks follow in order.
+    /// Headers and steps only apply to the main mirror, so configs with them are left alone.
+    pub fn promote_mirror(&mut self, url: &str) -> Result<()> {
+        if *self.mirror.url == *url {
+            return Ok(());
+        }
+
+        if !self.mirror.headers.is_empty() || !self.mirror.steps.is_empty() {
+            return Err(AppError::other(
+                "mirror.headers and mirror.steps only apply to the main mirror, change it by hand"
+            ));
+        }
+
+        let previous = std::mem::replace(&mut self.mirror.url, url.into());
+        self.mirror.fallbacks.retain(|fallback| **fallback != *url);
+        self.mirror.fallbacks.insert(0, previous);
+
+        Ok(())
+    }
+
+    /// Archives tried after every mirror reported a set missing, in order
+    pub fn archives(&self) -> Vec<MirrorConfig> {
+        self.mirror.archives.iter().map(|url| plain_mirror(&ascii_host(url))).collect()
+    }
+
     /// Merge CLI arguments into config
     pub fn merge_with_cli(
         mut self,
         mirror: Option<String>,
         skip_existing: bool,
+        notify: bool,
+        parallel_collections: Option<u8>,
     ) -> Self {
         if let Some(mirror_url) = mirror {
             self.mirror.url = mirror_url.into();
@@ -76,19 +473,231 @@ impl Config {
             self.download.skip_existing = true;
         }
 
+        if notify {
+            self.notifications.enabled = true;
+        }
+
+        if let Some(parallel) = parallel_collections {
+            self.download.parallel_collections = parallel;
+        }
+
         self
     }
 }
 
-/// Load configuration from file or use defaults
-pub fn load_config() -> Config {
-    if let Some(config_dir) = dirs::config_dir() {
-        let config_path = config_dir.join("osu-collect").join("config.toml");
-        if let Ok(contents) = std::fs::read_to_string(&config_path) {
-            if let Ok(config) = toml::from_str(&contents) {
-                return config;
-            }
+/// Mirror that is a plain URL template, without headers or steps
+fn plain_mirror(url: &str) -> MirrorConfig {
+    MirrorConfig {
+        url: url.into(),
+        fallbacks: Vec::new(),
+        archives: Vec::new(),
+        headers: BTreeMap::new(),
+        steps: Vec::new(),
+    }
+}
+
+/// URL template with an internationalized host in punycode, unchanged when it can't be parsed
+fn ascii_host(url: &str) -> Box<str> {
+    normalize_url_host(url).map_or_else(|| url.into(), String::into_boxed_str)
+}
+
+#[inline]
+fn is_http_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+/// `~/.config/osu-collect/config.toml` (platform config dir elsewhere)
+pub fn config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("osu-collect").join("config.toml"))
+}
+
+/// Copy of the previous config kept by `save_config`
+fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    PathBuf::from(backup)
+}
+
+/// Load configuration from file or use defaults.
+/// A config that doesn't match its checksum is an error, an unparsable one only warns.
+pub fn load_config() -> Result<Config> {
+    let Some(config_path) = config_path() else {
+        return Ok(Config::default());
+    };
+    let Ok(contents) = std::fs::read_to_string(&config_path) else {
+        return Ok(Config::default());
+    };
+
+    let contents = verify_checksum(&contents).map_err(|e| {
+        let backup = backup_path(&config_path);
+        let recovery = if backup.is_file() {
+            format!("restore {} or run `osu-collect setup`", backup.display())
+        } else {
+            "run `osu-collect setup`".to_string()
+        };
+        AppError::other_dynamic(
+            format!(
+                "{}: {}. If you edited it by hand, delete its first line, otherwise it is corrupted: {}",
+                config_path.display(), e, recovery
+            ).into_boxed_str()
+        )
+    })?;
+
+    match parse_config(contents) {
+        Ok(config) => Ok(config),
+        Err(e) => {
+            eprintln!("Warning: ignoring {}: {}", config_path.display(), e);
+            Ok(Config::default())
         }
     }
-    Config::default()
+}
+
+/// Write the config with a checksum line, keeping the previous file as `config.toml.bak`
+pub fn save_config(config: &Config, path: &Path) -> Result<()> {
+    let body = toml::to_string_pretty(config)
+        .map_err(|e| AppError::other_dynamic(e.to_string().into_boxed_str()))?;
+    let contents = format!(
+        "{}{} (delete this line after editing the file by hand)\n{}",
+        CHECKSUM_PREFIX, checksum(body.as_bytes()), body
+    );
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if path.is_file() {
+        std::fs::copy(path, backup_path(path))?;
+    }
+    write_atomic(path, contents)?;
+
+    Ok(())
+}
+
+/// Check and strip the checksum line of a config written by `save_config`.
+/// Configs without one (written by hand) are returned unchanged.
+fn verify_checksum(contents: &str) -> Result<&str> {
+    let Some(rest) = contents.strip_prefix(CHECKSUM_PREFIX) else {
+        return Ok(contents);
+    };
+
+    let (line, body) = rest.split_once('\n').unwrap_or((rest, ""));
+    let expected = line.split_whitespace().next().unwrap_or_default();
+    if checksum(body.as_bytes()) != expected {
+        return Err(AppError::other("the file doesn't match its checksum, it was changed after osu-collect wrote it"));
+    }
+
+    Ok(body)
+}
+
+fn parse_config(contents: &str) -> Result<Config> {
+    let mut table: toml::Table = toml::from_str(contents)
+        .map_err(|e| AppError::other_dynamic(e.to_string().into_boxed_str()))?;
+
+    let version = match table.get("version") {
+        None => 0,
+        Some(value) => value.as_integer()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| AppError::other_dynamic(
+                format!("Invalid config version {}, expected a non-negative integer", value).into_boxed_str()
+            ))?,
+    };
+
+    if version > CONFIG_VERSION {
+        eprintln!(
+            "Warning: config version {} is newer than supported ({}), some settings may be ignored",
+            version, CONFIG_VERSION
+        );
+    } else {
+        migrate(&mut table, version);
+    }
+
+    toml::Value::Table(table).try_into()
+        .map_err(|e| AppError::other_dynamic(e.to_string().into_boxed_str()))
+}
+
+/// Bring a config written for an older release up to the current version, one step at a time
+fn migrate(table: &mut toml::Table, from: u32) {
+    for version in from..CONFIG_VERSION {
+        match version {
+            // 0 -> 1: only the version field was added
+            0 => {}
+            _ => unreachable!("no migration from config version {}", version),
+        }
+    }
+
+    table.insert("version".into(), toml::Value::Integer(CONFIG_VERSION as i64));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINIMAL: &str = r#"
+        [mirror]
+        url = "https://api.nerinyan.moe/d/{id}"
+
+        [download]
+        skip_existing = false
+        concurrent = 3
+    "#;
+
+    #[test]
+    fn migrates_unversioned_config() {
+        let config = parse_config(MINIMAL).unwrap();
+
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.download.concurrent, 3);
+        assert!(config.download.mirror_limits);
+    }
+
+    #[test]
+    fn keeps_current_version() {
+        let config = parse_config(&format!("version = {}\n{}", CONFIG_VERSION, MINIMAL)).unwrap();
+
+        assert_eq!(config.version, CONFIG_VERSION);
+    }
+
+    #[test]
+    fn rejects_invalid_version() {
+        assert!(parse_config(&format!("version = -1\n{}", MINIMAL)).is_err());
+        assert!(parse_config(&format!("version = \"1\"\n{}", MINIMAL)).is_err());
+        assert!(parse_config(&format!("version = {}\n{}", u64::from(u32::MAX) + 1, MINIMAL)).is_err());
+    }
+
+    #[test]
+    fn checksum_accepts_saved_and_hand_written_configs() {
+        let body = format!("{}\n", MINIMAL.trim());
+        let saved = format!("{}{} (delete this line)\n{}", CHECKSUM_PREFIX, checksum(body.as_bytes()), body);
+
+        assert_eq!(verify_checksum(&saved).unwrap(), body);
+        assert_eq!(verify_checksum(MINIMAL).unwrap(), MINIMAL);
+    }
+
+    #[test]
+    fn checksum_rejects_changed_configs() {
+        let body = format!("{}\n", MINIMAL.trim());
+        let saved = format!("{}{} (delete this line)\n{}", CHECKSUM_PREFIX, checksum(body.as_bytes()), body);
+
+        assert!(verify_checksum(&saved.replace("concurrent = 3", "concurrent = 30")).is_err());
+        assert!(verify_checksum(&saved[..saved.len() - 10]).is_err());
+    }
+
+    #[test]
+    fn promote_mirror_keeps_the_others_as_fallbacks() {
+        let mut config = parse_config(MINIMAL).unwrap();
+        config.mirror.fallbacks = vec!["https://catboy.best/d/{id}".into(), "https://api.chimu.moe/v1/download/{id}?n=1".into()];
+
+        config.promote_mirror("https://api.chimu.moe/v1/download/{id}?n=1").unwrap();
+
+        assert_eq!(&*config.mirror.url, "https://api.chimu.moe/v1/download/{id}?n=1");
+        assert_eq!(config.mirror.fallbacks, ["https://api.nerinyan.moe/d/{id}".into(), "https://catboy.best/d/{id}".into()]);
+
+        config.mirror.headers.insert("Authorization".into(), "token".into());
+        assert!(config.promote_mirror("https://catboy.best/d/{id}").is_err());
+        assert!(config.promote_mirror("https://api.chimu.moe/v1/download/{id}?n=1").is_ok());
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        assert!(Config::default().validate().is_ok());
+    }
 }
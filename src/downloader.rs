@@ -1,57 +1,180 @@
+use crate::attributes::{self, FileMetadata};
+use crate::collector::Beatmapset;
+use crate::config::MirrorConfig;
 use crate::error::{AppError, Result};
-use crate::utils::sanitize_filename;
+use crate::manifest::Hasher;
+use crate::mirror;
+use crate::options::{FileExistsAction, HashAlgorithm};
+use crate::profile::{Phase, Profile};
+use crate::sink::{OutputSink, SinkFile};
+use crate::utils::{read_line, sanitize_filename};
 use futures_util::StreamExt;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::fs;
-use tokio::io::AsyncWriteExt;
 
 const MAX_FILE_SIZE: u32 = 100 * 1024 * 1024;
+/// Smallest plausible beatmapset archive, anything below is a broken mirror response
+const MIN_FILE_SIZE: u64 = 5 * 1024;
+/// Local file header and empty archive signatures of zip files (.osz is a zip)
+const ZIP_SIGNATURES: [&[u8; 4]; 2] = [b"PK\x03\x04", b"PK\x05\x06"];
 const DOWNLOAD_TIMEOUT_SECS: u64 = 60;
+/// Bounds of the adaptive write size, see `WriteBuffer`
+const MIN_WRITE_SIZE: usize = 16 * 1024;
+const MAX_WRITE_SIZE: usize = 1024 * 1024;
+/// How often a stalled download checks for Ctrl+C
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How often the throughput behind the write size is re-measured
+const THROUGHPUT_WINDOW: Duration = Duration::from_millis(250);
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum DownloadResult {
-    Success(Box<str>),
+    /// Saved filename, its content hash (`DownloadContext::hash`) and size in bytes
+    Success(Box<str>, Box<str>, u64),
     Skipped(Box<str>),
+    /// Existing file skipped by `file_exists_default` because no prompt could be shown
+    NeedsAttention(Box<str>),
     Failed(&'static str),
     FailedDynamic(Box<str>),
+    /// Not found (404) on every mirror that was tried
+    NotFound,
     Aborted,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum FileExistsAction {
-    Skip,
-    Overwrite,
-    Abort,
+/// Create HTTP client optimized for downloads, timing new connections into `profile`
+pub fn create_download_client(profile: &Profile) -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(DOWNLOAD_TIMEOUT_SECS))
+        .redirect(reqwest::redirect::Policy::limited(5));
+
+    if let Some(timer) = profile.connect_timer() {
+        builder = builder.connector_layer(timer);
+    }
+
+    builder.build().map_err(AppError::Network)
 }
 
-/// Create HTTP client optimized for downloads
-#[inline]
-pub fn create_download_client() -> Result<reqwest::Client> {
-    reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(DOWNLOAD_TIMEOUT_SECS))
-        .redirect(reqwest::redirect::Policy::limited(5))
-        .build()
-        .map_err(AppError::Network)
+/// Shared settings for every download in a run
+pub struct DownloadContext<'a> {
+    pub client: &'a reqwest::Client,
+    pub mirrors: &'a [MirrorConfig],
+    /// Index into `mirrors` tried first, switched by the mirror picker during a run
+    pub preferred_mirror: &'a AtomicUsize,
+    /// Request limits of the mirror presets, enforced unless turned off
+    pub limits: &'a mirror::RateLimits,
+    /// Last-resort sources for sets every mirror reported missing
+    pub archives: &'a [MirrorConfig],
+    pub output_dir: &'a Path,
+    /// Where downloaded files are stored
+    pub sink: &'a dyn OutputSink,
+    pub skip_existing: bool,
+    pub auto_overwrite: bool,
+    /// Whether stdin is a terminal that prompts can be answered from
+    pub interactive: bool,
+    /// Action applied instead of prompting when not interactive
+    pub file_exists_default: FileExistsAction,
+    /// Extension saved files are normalized to, without the dot
+    pub extension: &'a str,
+    /// Hash computed while streaming, recorded in the manifest
+    pub hash: HashAlgorithm,
+    /// Time limit for a whole transfer, after which the next mirror is tried
+    pub deadline: Option<Duration>,
+    /// Unix permission bits applied to downloaded files
+    pub file_mode: Option<u32>,
+    /// Collection source stored in the extended attributes of downloaded files, when enabled
+    pub metadata_collection: Option<&'a str>,
+    /// Unix `uid:gid` applied to downloaded files
+    pub owner: Option<(u32, u32)>,
+    pub shutdown: Arc<AtomicBool>,
+    pub profile: &'a Profile,
 }
 
-/// Download beatmap with streaming and async I/O
+/// Download beatmap, falling back to the next mirror when one fails and to the
+/// archives when every mirror reported the set missing
 pub async fn download_beatmap(
-    client: &reqwest::Client,
-    beatmapset_id: u32,
-    mirror_url_template: &str,
-    output_dir: &Path,
-    skip_existing: bool,
-    auto_overwrite: bool,
-    shutdown: Arc<AtomicBool>,
+    ctx: &DownloadContext<'_>,
+    beatmapset: &Beatmapset,
+) -> Result<DownloadResult> {
+    let mirrors: Vec<&MirrorConfig> = preferred_first(ctx.mirrors, ctx.preferred_mirror.load(Ordering::Relaxed)).collect();
+    let result = try_mirrors(ctx, beatmapset, &mirrors).await;
+
+    if !matches!(result, Ok(DownloadResult::NotFound)) || ctx.archives.is_empty() {
+        return result;
+    }
+
+    // Archives are best-effort, their failures don't change that the set is missing
+    let archives: Vec<&MirrorConfig> = ctx.archives.iter().collect();
+    match try_mirrors(ctx, beatmapset, &archives).await {
+        Ok(DownloadResult::NotFound | DownloadResult::Failed(_) | DownloadResult::FailedDynamic(_)) | Err(_) => result,
+        archived => archived,
+    }
+}
+
+/// `items` starting with the one at `first`, the others keep their order
+fn preferred_first<T>(items: &[T], first: usize) -> impl Iterator<Item = &T> {
+    items.get(first).into_iter()
+        .chain(items.iter().enumerate().filter(move |(index, _)| *index != first).map(|(_, item)| item))
+}
+
+/// Try each mirror in order until one delivers the set
+async fn try_mirrors(
+    ctx: &DownloadContext<'_>,
+    beatmapset: &Beatmapset,
+    mirrors: &[&MirrorConfig],
 ) -> Result<DownloadResult> {
-    let mirror_url = mirror_url_template.replace("{id}", &beatmapset_id.to_string());
+    let mut last_failure = None;
+
+    for mirror in mirrors {
+        if ctx.shutdown.load(Ordering::Acquire) {
+            return Ok(DownloadResult::Aborted);
+        }
+
+        let result = download_from_mirror(ctx, beatmapset, mirror).await;
+
+        match result {
+            Ok(DownloadResult::NotFound) => {}
+            Ok(DownloadResult::Failed(_) | DownloadResult::FailedDynamic(_)) | Err(_) => {
+                last_failure = Some(result);
+            }
+            done => return done,
+        }
+    }
+
+    // A download cut short by Ctrl+C is not a mirror failure
+    if ctx.shutdown.load(Ordering::Acquire) {
+        return Ok(DownloadResult::Aborted);
+    }
+
+    last_failure.unwrap_or(Ok(DownloadResult::NotFound))
+}
+
+/// Download beatmap from a single mirror with streaming and async I/O
+async fn download_from_mirror(
+    ctx: &DownloadContext<'_>,
+    beatmapset: &Beatmapset,
+    mirror: &MirrorConfig,
+) -> Result<DownloadResult> {
+    let beatmapset_id = beatmapset.id;
+
+    let Some(mut request) = mirror::build_download_request(
+        ctx.client, mirror, beatmapset_id, ctx.limits, &ctx.shutdown, ctx.profile,
+    ).await? else {
+        return Ok(DownloadResult::Aborted);
+    };
+    let request_started = ctx.profile.start();
+    if let Some(deadline) = ctx.deadline {
+        // Replaces the client timeout and covers the whole transfer, body included
+        request = request.timeout(deadline);
+    }
 
-    let response = match client.get(&mirror_url).send().await {
+    let response = match request.send().await {
         Ok(resp) => resp,
         Err(e) => {
-            return Ok(if e.is_timeout() {
+            return Ok(if e.is_timeout() && ctx.deadline.is_some() {
+                DownloadResult::Failed("Per-file deadline exceeded")
+            } else if e.is_timeout() {
                 DownloadResult::Failed("Connection timeout")
             } else if e.is_connect() {
                 DownloadResult::Failed("Connection failed")
@@ -61,10 +184,12 @@ pub async fn download_beatmap(
         }
     };
 
+    ctx.profile.record(Phase::Request, request_started);
+
     let status = response.status();
 
     if status == reqwest::StatusCode::NOT_FOUND {
-        return Ok(DownloadResult::Failed("Not found (404)"));
+        return Ok(DownloadResult::NotFound);
     }
 
     if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
@@ -78,27 +203,45 @@ pub async fn download_beatmap(
     }
 
     let content_length = response.content_length();
-    if let Some(len) = content_length {
-        if len > MAX_FILE_SIZE as u64 {
-            return Ok(DownloadResult::FailedDynamic(
-                format!("File too large ({} MB, max 100 MB)", len / 1024 / 1024).into_boxed_str()
-            ));
-        }
+    if let Some(len) = content_length
+        && len > MAX_FILE_SIZE as u64
+    {
+        return Ok(DownloadResult::FailedDynamic(
+            format!("File too large ({} MB, max 100 MB)", len / 1024 / 1024).into_boxed_str()
+        ));
     }
 
-    let filename = extract_filename_from_response(&response, beatmapset_id)?;
-    let sanitized_filename = sanitize_filename(&filename);
-    let output_path = output_dir.join(&sanitized_filename);
+    if content_length.is_some_and(|len| len < MIN_FILE_SIZE) {
+        return Ok(DownloadResult::Failed("Empty response from mirror"));
+    }
 
-    if output_path.exists() {
+    let filename = extract_filename_from_response(&response, beatmapset_id)?;
+    let filename = normalize_extension(&filename, ctx.extension);
+    let filename = match &beatmapset.slot {
+        Some(slot) => format!("{} - {}", slot, filename),
+        None => filename,
+    };
+    let sanitized_filename = match &beatmapset.subfolder {
+        // Forward slashes work as separator on Windows too
+        Some(subfolder) => format!("{}/{}", subfolder, sanitize_filename(&filename)),
+        None => sanitize_filename(&filename),
+    };
+    if ctx.sink.contains(ctx.output_dir, &sanitized_filename).await? {
         // Check if shutdown was triggered by another download
-        if shutdown.load(Ordering::Acquire) {
+        if ctx.shutdown.load(Ordering::Acquire) {
             return Ok(DownloadResult::Aborted);
         }
 
-        let action = determine_file_exists_action(skip_existing, auto_overwrite, &sanitized_filename, shutdown.clone())?;
+        // Files in an archive or on a server can't be replaced, they are always kept
+        let action = match ctx.sink.is_local() {
+            true => determine_file_exists_action(ctx, &sanitized_filename)?,
+            false => FileExistsAction::Skip,
+        };
 
         match action {
+            FileExistsAction::Skip if ctx.sink.is_local() && !ctx.interactive && !ctx.skip_existing => {
+                return Ok(DownloadResult::NeedsAttention(sanitized_filename.into_boxed_str()));
+            }
             FileExistsAction::Skip => {
                 return Ok(DownloadResult::Skipped(sanitized_filename.into_boxed_str()));
             }
@@ -109,39 +252,200 @@ pub async fn download_beatmap(
         }
     }
 
-    download_with_streaming(response, &output_path).await
-        .map(|_| DownloadResult::Success(sanitized_filename.into_boxed_str()))
+    download_with_streaming(ctx, response, &sanitized_filename, beatmapset_id).await
+        .map(|(hash, size)| DownloadResult::Success(sanitized_filename.into_boxed_str(), hash.into_boxed_str(), size))
 }
 
-/// Stream download to file with chunked writing
+/// Stream the download into the sink, returns the content hash and size
 async fn download_with_streaming(
+    ctx: &DownloadContext<'_>,
+    response: reqwest::Response,
+    filename: &str,
+    beatmapset_id: u32,
+) -> Result<(String, u64)> {
+    let source_url = response.url().to_string();
+    let file = ctx.sink.create(ctx.output_dir, filename).await?;
+    let stored = stream_to_sink(response, file, Hasher::new(ctx.hash), ctx.profile, &ctx.shutdown).await?;
+
+    // Attributes and permissions only exist for plain files
+    if ctx.sink.is_local() {
+        let output_path = ctx.output_dir.join(filename);
+        if let Some(collection) = ctx.metadata_collection {
+            attributes::write(&output_path, &FileMetadata { beatmapset_id, collection, source_url: &source_url });
+        }
+
+        apply_file_permissions(&output_path, ctx.file_mode, ctx.owner)?;
+    }
+
+    Ok(stored)
+}
+
+/// Set the configured mode and owner on a downloaded file
+#[cfg(unix)]
+fn apply_file_permissions(path: &Path, mode: Option<u32>, owner: Option<(u32, u32)>) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if let Some(mode) = mode {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    }
+
+    if let Some((uid, gid)) = owner {
+        std::os::unix::fs::chown(path, Some(uid), Some(gid)).map_err(|e| {
+            AppError::other_dynamic(
+                format!("Failed to change owner to {}:{} (requires root): {}", uid, gid, e).into_boxed_str()
+            )
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn apply_file_permissions(_path: &Path, _mode: Option<u32>, _owner: Option<(u32, u32)>) -> Result<()> {
+    Ok(())
+}
+
+/// Collects received chunks and writes them in batches sized to the connection's throughput:
+/// roughly 100 ms worth of data, so fast links make few large writes and slow links still
+/// reach the disk regularly. Progress bars count whole beatmapsets, so there is no per-byte
+/// progress sampling to adapt alongside.
+struct WriteBuffer {
+    data: Vec<u8>,
+    target: usize,
+    window_bytes: usize,
+    window_started: Instant,
+}
+
+impl WriteBuffer {
+    fn new() -> Self {
+        WriteBuffer {
+            data: Vec::with_capacity(MIN_WRITE_SIZE),
+            target: MIN_WRITE_SIZE,
+            window_bytes: 0,
+            window_started: Instant::now(),
+        }
+    }
+
+    /// Add a chunk, returns whether the buffer should be written out
+    fn push(&mut self, chunk: &[u8]) -> bool {
+        self.data.extend_from_slice(chunk);
+        self.window_bytes += chunk.len();
+
+        let elapsed = self.window_started.elapsed();
+        if elapsed >= THROUGHPUT_WINDOW {
+            let per_second = self.window_bytes as f64 / elapsed.as_secs_f64();
+            self.target = ((per_second / 10.0) as usize).clamp(MIN_WRITE_SIZE, MAX_WRITE_SIZE);
+            self.window_bytes = 0;
+            self.window_started = Instant::now();
+        }
+
+        self.data.len() >= self.target
+    }
+}
+
+/// Stream a download into a sink file with chunked writing, returns the content hash and size.
+/// The file is completed on success and thrown away otherwise.
+async fn stream_to_sink(
     response: reqwest::Response,
-    output_path: &Path,
-) -> Result<()> {
-    let mut file = fs::File::create(output_path).await?;
+    mut file: Box<dyn SinkFile>,
+    mut hasher: Hasher,
+    profile: &Profile,
+    shutdown: &AtomicBool,
+) -> Result<(String, u64)> {
+    let body_started = profile.start();
+    let mut disk_time = Duration::ZERO;
+    let mut hash_time = Duration::ZERO;
+
+    let downloaded = match write_body(response, &mut *file, &mut hasher, profile, shutdown, &mut disk_time, &mut hash_time).await {
+        Ok(downloaded) => downloaded,
+        Err(e) => {
+            file.abort().await;
+            return Err(e);
+        }
+    };
+
+    let disk_started = Instant::now();
+    file.finish().await?;
+    disk_time += disk_started.elapsed();
+
+    if let Some(body_started) = body_started {
+        profile.add(Phase::Body, body_started.elapsed().saturating_sub(disk_time + hash_time));
+        profile.add(Phase::Hash, hash_time);
+        profile.add(Phase::Disk, disk_time);
+        profile.count_download();
+    }
+
+    Ok((hasher.finish(), downloaded))
+}
+
+/// Write the response body into `file`, returns the number of bytes received
+async fn write_body(
+    response: reqwest::Response,
+    file: &mut dyn SinkFile,
+    hasher: &mut Hasher,
+    profile: &Profile,
+    shutdown: &AtomicBool,
+    disk_time: &mut Duration,
+    hash_time: &mut Duration,
+) -> Result<u64> {
     let mut stream = response.bytes_stream();
     let mut downloaded: u64 = 0;
+    let mut signature: Vec<u8> = Vec::with_capacity(4);
+    let mut buffer = WriteBuffer::new();
 
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk.map_err(AppError::Network)?;
+    loop {
+        if shutdown.load(Ordering::Acquire) {
+            return Err(AppError::other("Download cancelled"));
+        }
+
+        // Wake up regularly on stalled connections so Ctrl+C doesn't wait for the next chunk
+        let chunk = match tokio::time::timeout(CANCEL_POLL_INTERVAL, stream.next()).await {
+            Ok(Some(Err(e))) if e.is_timeout() => return Err(AppError::other("Transfer timed out")),
+            Ok(Some(chunk)) => chunk.map_err(AppError::Network)?,
+            Ok(None) => break,
+            Err(_) => continue,
+        };
 
         downloaded += chunk.len() as u64;
 
+        if signature.len() < 4 {
+            let needed = (4 - signature.len()).min(chunk.len());
+            signature.extend_from_slice(&chunk[..needed]);
+
+            if signature.len() == 4 && !ZIP_SIGNATURES.iter().any(|sig| sig[..] == signature[..]) {
+                return Err(AppError::other("Mirror response is not an .osz/.zip archive"));
+            }
+        }
+
         if downloaded > MAX_FILE_SIZE as u64 {
-            file.shutdown().await?;
-            let _ = fs::remove_file(output_path).await;
             return Err(AppError::other_dynamic(
                 format!("File too large ({} MB, max 100 MB)", downloaded / 1024 / 1024).into_boxed_str()
             ));
         }
 
-        file.write_all(&chunk).await?;
+        let hash_started = profile.start();
+        hasher.update(&chunk);
+        *hash_time += hash_started.map_or(Duration::ZERO, |started| started.elapsed());
+
+        if buffer.push(&chunk) {
+            let disk_started = Instant::now();
+            file.write(&buffer.data).await?;
+            buffer.data.clear();
+            *disk_time += disk_started.elapsed();
+        }
+    }
+
+    if downloaded < MIN_FILE_SIZE {
+        return Err(AppError::other_dynamic(
+            format!("Empty response from mirror ({} bytes)", downloaded).into_boxed_str()
+        ));
     }
 
-    file.flush().await?;
-    file.shutdown().await?;
+    let disk_started = Instant::now();
+    file.write(&buffer.data).await?;
+    *disk_time += disk_started.elapsed();
 
-    Ok(())
+    Ok(downloaded)
 }
 
 /// Extract filename from HTTP response headers
@@ -149,17 +453,33 @@ fn extract_filename_from_response(
     response: &reqwest::Response,
     beatmapset_id: u32,
 ) -> Result<String> {
-    if let Some(content_disposition) = response.headers().get(reqwest::header::CONTENT_DISPOSITION) {
-        if let Ok(value) = content_disposition.to_str() {
-            if let Some(filename) = parse_content_disposition(value) {
-                return Ok(filename);
-            }
-        }
+    if let Some(content_disposition) = response.headers().get(reqwest::header::CONTENT_DISPOSITION)
+        && let Ok(value) = content_disposition.to_str()
+        && let Some(filename) = parse_content_disposition(value)
+    {
+        return Ok(filename);
     }
 
     Ok(format!("{}.osz", beatmapset_id))
 }
 
+/// Make sure the filename ends with the configured extension, replacing `.osz`/`.zip`
+fn normalize_extension(filename: &str, extension: &str) -> String {
+    let lower = filename.to_ascii_lowercase();
+
+    if lower.ends_with(&format!(".{}", extension.to_ascii_lowercase())) {
+        return filename.to_string();
+    }
+
+    let stem = if lower.ends_with(".osz") || lower.ends_with(".zip") {
+        &filename[..filename.len() - 4]
+    } else {
+        filename
+    };
+
+    format!("{}.{}", stem, extension)
+}
+
 /// Parse Content-Disposition header
 fn parse_content_disposition(value: &str) -> Option<String> {
     for part in value.split(';') {
@@ -179,19 +499,24 @@ fn parse_content_disposition(value: &str) -> Option<String> {
 
 /// Determine action when file exists
 fn determine_file_exists_action(
-    skip_existing: bool,
-    auto_overwrite: bool,
+    ctx: &DownloadContext<'_>,
     filename: &str,
-    shutdown: Arc<AtomicBool>,
 ) -> Result<FileExistsAction> {
-    if skip_existing {
+    if ctx.skip_existing {
         return Ok(FileExistsAction::Skip);
     }
 
-    if auto_overwrite {
+    if ctx.auto_overwrite {
         return Ok(FileExistsAction::Overwrite);
     }
 
+    if !ctx.interactive {
+        if ctx.file_exists_default == FileExistsAction::Abort {
+            ctx.shutdown.store(true, Ordering::Release);
+        }
+        return Ok(ctx.file_exists_default);
+    }
+
     eprintln!("\nFile already exists: {}", filename);
     eprintln!("Options:");
     eprintln!("  [s] Skip this file");
@@ -200,14 +525,13 @@ fn determine_file_exists_action(
     eprint!("Choose action (s/o/a): ");
     std::io::Write::flush(&mut std::io::stderr())?;
 
-    let mut input = String::new();
-    std::io::stdin().read_line(&mut input)?;
+    let input = read_line()?;
 
     match input.trim().to_lowercase().as_str() {
         "s" => Ok(FileExistsAction::Skip),
         "o" => Ok(FileExistsAction::Overwrite),
         "a" => {
-            shutdown.store(true, Ordering::Release);
+            ctx.shutdown.store(true, Ordering::Release);
             Ok(FileExistsAction::Abort)
         }
         _ => {
@@ -219,9 +543,9 @@ fn determine_file_exists_action(
 
 /// Validate and prepare download directory
 pub async fn validate_and_prepare_directory(directory: &str) -> Result<PathBuf> {
-    let expanded_path = if directory.starts_with("~/") {
+    let expanded_path = if let Some(stripped) = directory.strip_prefix("~/") {
         if let Some(home_dir) = dirs::home_dir() {
-            home_dir.join(&directory[2..])
+            home_dir.join(stripped)
         } else {
             PathBuf::from(directory)
         }
@@ -231,8 +555,7 @@ pub async fn validate_and_prepare_directory(directory: &str) -> Result<PathBuf>
 
     if !expanded_path.exists() {
         fs::create_dir_all(&expanded_path).await.map_err(|e| {
-            AppError::FileSystem(std::io::Error::new(
-                std::io::ErrorKind::Other,
+            AppError::FileSystem(std::io::Error::other(
                 format!("Failed to create directory '{}': {}", expanded_path.display(), e),
             ))
         })?;
@@ -258,3 +581,45 @@ pub async fn validate_and_prepare_directory(directory: &str) -> Result<PathBuf>
         ))),
     }
 }
+
+/// Check whether a directory looks like an osu! `Songs` folder (or a folder inside one)
+pub fn is_osu_songs_folder(path: &Path) -> bool {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+
+    path.ancestors().any(|dir| {
+        let is_songs = dir.file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.eq_ignore_ascii_case("songs"));
+
+        is_songs && dir.parent().is_some_and(|osu_dir| {
+            osu_dir.join("osu!.exe").exists() || osu_dir.join("osu!.db").exists()
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preferred_mirror_is_tried_first() {
+        let order = |first| preferred_first(&[1, 2, 3], first).copied().collect::<Vec<_>>();
+
+        assert_eq!(order(0), [1, 2, 3]);
+        assert_eq!(order(2), [3, 1, 2]);
+        assert_eq!(order(5), [1, 2, 3]);
+    }
+
+    #[test]
+    fn normalize_extension_replaces_archive_extensions() {
+        assert_eq!(normalize_extension("1 Artist - Title.osz", "zip"), "1 Artist - Title.zip");
+        assert_eq!(normalize_extension("1 Artist - Title.ZIP", "osz"), "1 Artist - Title.osz");
+        assert_eq!(normalize_extension("1 Artist - Title", "osz"), "1 Artist - Title.osz");
+    }
+
+    #[test]
+    fn normalize_extension_keeps_matching_extension() {
+        assert_eq!(normalize_extension("1 Artist - Title.OSZ", "osz"), "1 Artist - Title.OSZ");
+        assert_eq!(normalize_extension("1 v1.2.osz", "osz"), "1 v1.2.osz");
+    }
+}
@@ -0,0 +1,85 @@
+use crate::collector::Collection;
+use crate::error::{AppError, Result};
+use crate::manifest::Manifest;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ExportFormat {
+    /// Newline-separated beatmap MD5s (`hashes.txt`)
+    Hashes,
+    /// Beatmap MD5s with set and beatmap IDs (`hashes.json`)
+    Json,
+    /// Beatmap MD5 to set ID and local filename (`hashes.tsv`)
+    Tsv,
+}
+
+#[derive(Serialize)]
+struct JsonExport<'a> {
+    id: u32,
+    name: &'a str,
+    beatmaps: Vec<JsonBeatmap<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonBeatmap<'a> {
+    beatmapset_id: u32,
+    beatmap_id: u32,
+    md5: &'a str,
+}
+
+/// Write the collection's beatmap checksums for third-party collection managers.
+/// Local filenames come from the manifest. Returns the path of the written file.
+pub fn write_export(
+    collection: &Collection,
+    manifest: &Manifest,
+    output_dir: &Path,
+    format: ExportFormat,
+) -> Result<PathBuf> {
+    let beatmaps = collection.beatmapsets.iter().flat_map(|beatmapset| {
+        beatmapset.beatmaps.iter().map(move |beatmap| (beatmapset.id, beatmap))
+    });
+
+    let (path, contents) = match format {
+        ExportFormat::Hashes => {
+            let mut contents = String::new();
+            for (_, beatmap) in beatmaps {
+                contents.push_str(&beatmap.checksum);
+                contents.push('\n');
+            }
+            (output_dir.join("hashes.txt"), contents)
+        }
+        ExportFormat::Json => {
+            let export = JsonExport {
+                id: collection.id,
+                name: &collection.name,
+                beatmaps: beatmaps
+                    .map(|(beatmapset_id, beatmap)| JsonBeatmap {
+                        beatmapset_id,
+                        beatmap_id: beatmap.id,
+                        md5: &beatmap.checksum,
+                    })
+                    .collect(),
+            };
+            (output_dir.join("hashes.json"), serde_json::to_string_pretty(&export)?)
+        }
+        ExportFormat::Tsv => {
+            // Sets that weren't downloaded get an empty filename
+            let mut contents = String::from("md5\tbeatmapset_id\tfilename\n");
+            for (beatmapset_id, beatmap) in beatmaps {
+                let filename = manifest.files.get(&beatmapset_id).map_or("", |entry| &entry.filename);
+                contents.push_str(&format!("{}\t{}\t{}\n", beatmap.checksum, beatmapset_id, filename));
+            }
+            (output_dir.join("hashes.tsv"), contents)
+        }
+    };
+
+    std::fs::write(&path, contents).map_err(|e| {
+        AppError::other_dynamic(
+            format!("Failed to write {}: {}", path.display(), e).into_boxed_str()
+        )
+    })?;
+
+    Ok(path)
+}
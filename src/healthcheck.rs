@@ -0,0 +1,53 @@
+use crate::config::Config;
+use std::time::Duration;
+use url::Url;
+
+const HEALTHCHECK_TIMEOUT_SECS: u64 = 10;
+
+/// Check that osu!collector and every configured mirror answer, returns the process exit code.
+/// Any HTTP response counts as reachable, only connection failures and timeouts are unhealthy.
+pub async fn run(config: &Config) -> i32 {
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(HEALTHCHECK_TIMEOUT_SECS))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            println!("UNHEALTHY: failed to create HTTP client: {}", e);
+            return 1;
+        }
+    };
+
+    let mut targets = vec![("osu!collector".to_string(), "https://osucollector.com".to_string())];
+    for mirror in config.mirrors() {
+        if let Some(origin) = origin(&mirror.url) {
+            targets.push((format!("mirror {}", origin), origin));
+        }
+    }
+
+    let mut healthy = true;
+
+    for (name, url) in targets {
+        match client.head(&url).send().await {
+            Ok(response) => println!("ok: {} (HTTP {})", name, response.status().as_u16()),
+            Err(e) => {
+                healthy = false;
+                println!("failed: {} ({})", name, e);
+            }
+        }
+    }
+
+    if healthy {
+        println!("HEALTHY");
+        0
+    } else {
+        println!("UNHEALTHY");
+        1
+    }
+}
+
+/// Scheme and host of a mirror URL template
+pub fn origin(template: &str) -> Option<String> {
+    let url = Url::parse(&template.replace("{id}", "1")).ok()?;
+    Some(format!("{}://{}", url.scheme(), url.host_str()?))
+}
@@ -0,0 +1,187 @@
+use crate::error::{AppError, Result};
+use crate::utils::format_timestamp;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Journal size after which it is rotated to `journal.log.1`
+const MAX_JOURNAL_SIZE: u64 = 1024 * 1024;
+const JOURNAL_VERSION: u32 = 1;
+/// First line of every journal file, followed by the version
+const HEADER_PREFIX: &str = "# osu-collect journal v";
+
+/// One collection of a run, written as a single `key=value` line
+pub struct Entry<'a> {
+    /// `collection:<id>`, `tournament:<id>` or `<source>:<id>`
+    pub source: &'a str,
+    pub downloaded: u16,
+    pub skipped: u16,
+    pub failed: u16,
+    pub bytes: u64,
+    pub duration: Duration,
+    /// `ok`, `failed`, `interrupted` or `error`
+    pub status: &'a str,
+    /// Free-form labels given with `--tag`
+    pub tags: &'a [String],
+}
+
+/// A parsed journal line
+pub struct Record {
+    pub timestamp: Box<str>,
+    fields: Vec<(Box<str>, Box<str>)>,
+}
+
+impl Record {
+    fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        let timestamp = parts.next()?.into();
+        let fields = parts
+            .filter_map(|part| part.split_once('='))
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
+
+        Some(Record { timestamp, fields })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields.iter().find(|(name, _)| **name == *key).map(|(_, value)| &**value)
+    }
+
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.get("tags").into_iter().flat_map(|tags| tags.split(','))
+    }
+}
+
+/// `~/.local/share/osu-collect/journal.log` (platform data dir elsewhere)
+pub fn journal_path() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|dir| dir.join("osu-collect").join("journal.log"))
+}
+
+/// Append entries to the journal. Failures only warn, the journal is never worth failing a run.
+pub fn append(entries: &[Entry<'_>]) {
+    let Some(path) = journal_path() else { return };
+
+    if let Err(e) = write_entries(&path, entries) {
+        eprintln!("\x1b[33m⚠\x1b[0m Warning: failed to write {}: {}", path.display(), e);
+    }
+}
+
+fn write_entries(path: &PathBuf, entries: &[Entry<'_>]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    if std::fs::metadata(path).is_ok_and(|meta| meta.len() > MAX_JOURNAL_SIZE) {
+        let mut rotated = path.as_os_str().to_owned();
+        rotated.push(".1");
+        std::fs::rename(path, rotated)?;
+    }
+
+    let timestamp = timestamp();
+    let mut lines = String::new();
+    if std::fs::metadata(path).map_or(true, |meta| meta.len() == 0) {
+        lines.push_str(&format!("{}{}\n", HEADER_PREFIX, JOURNAL_VERSION));
+    }
+
+    for entry in entries {
+        let tags: Vec<String> = entry.tags.iter()
+            .map(|tag| tag.trim().replace(char::is_whitespace, "_"))
+            .filter(|tag| !tag.is_empty())
+            .collect();
+
+        lines.push_str(&format!(
+            "{} source={} downloaded={} skipped={} failed={} bytes={} duration={:.1} status={}{}\n",
+            timestamp,
+            entry.source.replace(char::is_whitespace, "_"),
+            entry.downloaded,
+            entry.skipped,
+            entry.failed,
+            entry.bytes,
+            entry.duration.as_secs_f64(),
+            entry.status,
+            if tags.is_empty() { String::new() } else { format!(" tags={}", tags.join(",")) },
+        ));
+    }
+
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(lines.as_bytes())
+}
+
+/// Read the rotated and current journal, oldest entries first
+pub fn read() -> Result<Vec<Record>> {
+    let path = journal_path().ok_or(AppError::other("Could not determine the data directory"))?;
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".1");
+
+    let mut records = Vec::new();
+    for path in [PathBuf::from(rotated), path] {
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        let version = journal_version(&contents).ok_or_else(|| AppError::other_dynamic(
+            format!("Invalid journal header in {}", path.display()).into_boxed_str()
+        ))?;
+        if version > JOURNAL_VERSION {
+            return Err(AppError::other_dynamic(
+                format!("Journal version {} of {} is newer than supported ({})", version, path.display(), JOURNAL_VERSION)
+                    .into_boxed_str()
+            ));
+        }
+
+        // 0 -> 1: only the header was added, the lines are unchanged
+        records.extend(contents.lines().filter(|line| !line.starts_with('#')).filter_map(Record::parse));
+    }
+
+    Ok(records)
+}
+
+/// Version from the header of a journal file, 0 for files written before the header existed
+fn journal_version(contents: &str) -> Option<u32> {
+    match contents.lines().next().and_then(|line| line.strip_prefix(HEADER_PREFIX)) {
+        Some(version) => version.trim().parse().ok(),
+        None => Some(0),
+    }
+}
+
+/// Print past collections, only those carrying every tag in `tags` when given
+pub fn print_history(tags: &[String]) -> Result<()> {
+    let records: Vec<Record> = read()?
+        .into_iter()
+        .filter(|record| tags.iter().all(|tag| record.tags().any(|t| t.eq_ignore_ascii_case(tag.trim()))))
+        .collect();
+
+    if records.is_empty() {
+        println!("No collections in the history{}", if tags.is_empty() { "" } else { " with these tags" });
+        return Ok(());
+    }
+
+    for record in &records {
+        let field = |key| record.get(key).unwrap_or("-");
+        let megabytes = field("bytes").parse::<u64>().map_or(0.0, |bytes| bytes as f64 / 1024.0 / 1024.0);
+        let tags: Vec<&str> = record.tags().collect();
+
+        println!(
+            "{}  {:<24} {:<11} downloaded {:>4}, failed {:>3}, {:>8.1} MB{}",
+            record.timestamp,
+            field("source"),
+            field("status"),
+            field("downloaded"),
+            field("failed"),
+            megabytes,
+            if tags.is_empty() { String::new() } else { format!("  [{}]", tags.join(", ")) },
+        );
+    }
+
+    Ok(())
+}
+
+/// Current UTC time as `YYYY-MM-DDTHH:MM:SSZ`
+fn timestamp() -> String {
+    format_timestamp(SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as i64)
+}
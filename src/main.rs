@@ -1,28 +1,68 @@
+mod attributes;
+mod backup;
+mod batch;
+mod bench;
+mod cache;
 mod collector;
 mod collection;
 mod config;
 mod downloader;
 mod error;
+mod export;
+mod healthcheck;
+mod journal;
+mod manifest;
+mod mirror;
+mod notify;
+mod options;
+mod picker;
+mod profile;
+mod selftest;
+mod setup;
+mod sink;
+mod progress;
+mod state;
+mod subset;
 mod utils;
+mod verify;
+mod zip;
 
 #[cfg(windows)]
 mod windows_init;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use error::{AppError, Result};
 use futures_util::stream::{self, StreamExt};
-use indicatif::{ProgressBar, ProgressStyle};
-use std::sync::atomic::{AtomicBool, Ordering};
+use indicatif::MultiProgress;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// Most pages of failed beatmapsets `--open-failed` opens, so big failures don't flood the browser
+const MAX_OPENED_PAGES: usize = 10;
 
 #[derive(Parser, Debug)]
 #[command(name = "osu-collect")]
 #[command(version = env!("CARGO_PKG_VERSION"))]
 #[command(about = "a program to download osu map collections from osu!collector for free", long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
-    /// Collection URL or ID
-    #[arg(short, long)]
-    collection: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Collection or tournament URL, collection ID, or <source>:<id> of a custom source
+    #[arg(short, long, required_unless_present = "collection_list", conflicts_with = "collection_list")]
+    collection: Option<String>,
+
+    /// File with one collection URL or ID per line
+    #[arg(short = 'l', long, value_name = "FILE")]
+    collection_list: Option<PathBuf>,
+
+    /// Number of collections from the list downloaded at the same time
+    #[arg(long, value_name = "N")]
+    parallel_collections: Option<u8>,
 
     /// Download directory
     #[arg(short, long)]
@@ -39,6 +79,125 @@ struct Cli {
     /// Skip existing files
     #[arg(long)]
     skip_existing: bool,
+
+    /// Download into an output folder that already holds other files without asking
+    #[arg(long)]
+    allow_nonempty: bool,
+
+    #[command(flatten)]
+    options: batch::CollectionOptions,
+
+    /// Print a breakdown of time spent fetching, connecting, waiting, downloading, hashing and writing
+    #[arg(long)]
+    profile: bool,
+
+    /// Show desktop notifications on completion and failure bursts
+    #[arg(long)]
+    notify: bool,
+
+    /// Progress output style
+    #[arg(long, value_enum, default_value_t = progress::ProgressMode::Auto)]
+    progress: progress::ProgressMode,
+
+    /// Comma-separated labels stored with this run in the history, e.g. "tournament,2024"
+    #[arg(long, value_name = "TAGS", value_delimiter = ',')]
+    tag: Vec<String>,
+
+    /// Also merge the collection into an existing collection.db (e.g. of your osu! install),
+    /// after showing a preview of the changes
+    #[arg(long, value_name = "FILE")]
+    merge_into: Option<PathBuf>,
+
+    /// Open the osu! website pages of failed beatmapsets (at most 10) at the end of the run
+    #[arg(long)]
+    open_failed: bool,
+
+    /// Don't space out requests to the mirror presets to the built-in limits
+    /// (estimates, the operators don't publish any)
+    #[arg(long)]
+    ignore_mirror_limits: bool,
+
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check that the config is valid and osu!collector and the mirrors are reachable
+    Healthcheck,
+
+    /// Check downloaded files of a collection folder against its manifest
+    Verify {
+        /// Collection folder
+        directory: PathBuf,
+
+        /// Re-hash every file, even when its size and mtime are unchanged
+        #[arg(long)]
+        deep: bool,
+
+        /// Only report, don't quarantine, re-download or update the manifest
+        #[arg(long)]
+        read_only: bool,
+
+        /// Print a single Nagios-style status line (implies --read-only)
+        #[arg(long)]
+        nagios: bool,
+    },
+
+    /// Write a shareable list of IDs and checksums of the downloaded sets of a collection folder
+    ExportSubset {
+        /// Collection folder
+        directory: PathBuf,
+
+        /// Only include sets and difficulties matching: status=NAME, sr>=N, sr<=N,
+        /// artist~TEXT, title~TEXT or diff~TEXT (can be repeated)
+        #[arg(long, value_name = "FILTER", value_parser = subset::parse_filter)]
+        filter: Vec<subset::Filter>,
+
+        /// Output file, `.json` for JSON and CSV otherwise
+        #[arg(long, value_name = "FILE")]
+        to: PathBuf,
+    },
+
+    /// Pick a mirror for your region, check it from this machine and write the config file
+    Setup,
+
+    /// Show past downloads from the journal
+    History {
+        #[command(subcommand)]
+        command: HistoryCommand,
+    },
+
+    /// Download a small known-good beatmapset through the configured mirrors to check the setup
+    Selftest,
+
+    /// Roll back a file of an osu! install (e.g. a merged collection.db) to a backup
+    RestoreBackup {
+        /// File to restore
+        file: PathBuf,
+
+        /// Backup to restore instead of the newest one
+        #[arg(long, value_name = "BACKUP")]
+        from: Option<PathBuf>,
+
+        /// Only list the available backups, newest first
+        #[arg(long)]
+        list: bool,
+    },
+
+    /// Measure write speed of a directory and warn when the disk would be the bottleneck
+    BenchDisk {
+        /// Directory to benchmark
+        directory: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum HistoryCommand {
+    /// List past collections, oldest first
+    List {
+        /// Only show collections carrying this tag (can be repeated, all must match)
+        #[arg(long)]
+        tag: Vec<String>,
+    },
 }
 
 impl Cli {
@@ -49,10 +208,67 @@ impl Cli {
             ));
         }
 
+        if let Some(db_path) = &self.merge_into
+            && !db_path.is_file()
+        {
+            return Err(AppError::other_dynamic(
+                format!("--merge-into: {} is not a file", db_path.display()).into_boxed_str()
+            ));
+        }
+
         Ok(())
     }
 }
 
+/// Everything shared between the collections of a run
+struct RunContext<'a> {
+    cli: &'a Cli,
+    config: &'a config::Config,
+    base_dir: PathBuf,
+    collection_client: reqwest::Client,
+    download_client: reqwest::Client,
+    mirrors: Vec<config::MirrorConfig>,
+    /// Index into `mirrors` tried first, switched with the mirror picker
+    preferred_mirror: Arc<AtomicUsize>,
+    archives: Vec<config::MirrorConfig>,
+    limits: mirror::RateLimits,
+    sink: Arc<dyn sink::OutputSink>,
+    ui: MultiProgress,
+    progress: progress::ProgressMode,
+    /// Global download slots shared by all collections
+    slots: Arc<Semaphore>,
+    shutdown: Arc<AtomicBool>,
+    profile: profile::Profile,
+    /// Pipeline queue depths, tracked with `--profile`
+    queues: Option<Arc<progress::QueueDepths>>,
+    failure_burst: notify::FailureBurst,
+    interactive: bool,
+    batch: bool,
+    #[cfg(windows)]
+    taskbar: Option<windows_init::TaskbarProgress>,
+    #[cfg(windows)]
+    taskbar_progress: (std::sync::atomic::AtomicU64, std::sync::atomic::AtomicU64),
+}
+
+/// Outcome of downloading a single collection
+struct CollectionReport {
+    name: Box<str>,
+    /// Parsed source, e.g. `collection:17503`
+    source: Box<str>,
+    downloaded: u16,
+    skipped: u16,
+    failed: u16,
+    needs_attention: u16,
+    known_unavailable: usize,
+    /// Bytes of the files downloaded in this run
+    bytes: u64,
+    duration: std::time::Duration,
+    aborted: bool,
+    /// Sets that still need to be downloaded, failed ones included
+    remaining: usize,
+    failed_ids: Vec<u32>,
+}
+
 #[tokio::main]
 async fn main() {
     #[cfg(windows)]
@@ -65,14 +281,127 @@ async fn main() {
         std::process::exit(1);
     }
 
-    let config = config::load_config()
-        .merge_with_cli(cli.mirror.clone(), cli.skip_existing);
+    let config = match config::load_config() {
+        Ok(config) => config,
+        // Setup replaces the config, so it starts from the defaults
+        Err(_) if matches!(cli.command, Some(Command::Setup)) => config::Config::default(),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let config = config
+        .merge_with_cli(cli.mirror.clone(), cli.skip_existing, cli.notify, cli.parallel_collections);
 
-    if let Err(e) = config.validate() {
+    // Setup replaces the config, an invalid one mustn't stop it
+    if !matches!(cli.command, Some(Command::Setup))
+        && let Err(e) = config.validate()
+    {
         eprintln!("error: {}", e);
         std::process::exit(1);
     }
 
+    match &cli.command {
+        Some(Command::Healthcheck) => {
+            std::process::exit(healthcheck::run(&config).await);
+        }
+        Some(Command::Verify { directory, deep, read_only, nagios }) => {
+            let read_only = *read_only || *nagios;
+            let result = match verify::verify_collection(directory, *deep, read_only) {
+                Ok(report) if read_only => Ok(report),
+                Ok(mut report) => verify::redownload_corrupt(&config, directory, &mut report)
+                    .await
+                    .map(|()| report),
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(report) => {
+                    if *nagios {
+                        verify::print_nagios(&report);
+                    } else {
+                        verify::print_report(&report);
+                    }
+                    std::process::exit(report.health().exit_code());
+                }
+                Err(e) if *nagios => {
+                    println!("OSU-COLLECT VERIFY UNKNOWN - {}", e);
+                    std::process::exit(verify::EXIT_UNKNOWN);
+                }
+                Err(e) => {
+                    eprintln!("\x1b[31m✗ error: {}\x1b[0m", e);
+                    std::process::exit(verify::EXIT_UNKNOWN);
+                }
+            }
+        }
+        Some(Command::ExportSubset { directory, filter, to }) => {
+            match subset::export_subset(directory, filter, to) {
+                Ok((sets, beatmaps)) => {
+                    println!("\x1b[32m✓\x1b[0m Exported {} beatmapsets ({} beatmaps) to {}", sets, beatmaps, to.display());
+                    std::process::exit(0);
+                }
+                Err(e) => {
+                    eprintln!("\x1b[31m✗ error: {}\x1b[0m", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(Command::Setup) => {
+            if let Err(e) = setup::run().await {
+                eprintln!("\x1b[31m✗ error: {}\x1b[0m", e);
+                std::process::exit(1);
+            }
+            std::process::exit(0);
+        }
+        Some(Command::History { command: HistoryCommand::List { tag } }) => {
+            if let Err(e) = journal::print_history(tag) {
+                eprintln!("\x1b[31m✗ error: {}\x1b[0m", e);
+                std::process::exit(1);
+            }
+            std::process::exit(0);
+        }
+        Some(Command::Selftest) => {
+            if let Err(e) = selftest::run(&config).await {
+                eprintln!("\x1b[31m✗ selftest failed: {}\x1b[0m", e);
+                std::process::exit(1);
+            }
+            println!("\x1b[32mSetup works, ready to download.\x1b[0m");
+            std::process::exit(0);
+        }
+        Some(Command::RestoreBackup { file, from, list }) => {
+            let result = if *list {
+                backup::list(file).map(|backups| {
+                    if backups.is_empty() {
+                        println!("No backups of {}", file.display());
+                    }
+                    for backup in backups {
+                        println!("{}", backup.display());
+                    }
+                })
+            } else {
+                backup::restore(file, from.as_deref()).map(|backup| {
+                    println!("\x1b[32m✓\x1b[0m Restored {} from {}", file.display(), backup.display());
+                })
+            };
+            if let Err(e) = result {
+                eprintln!("\x1b[31m✗ error: {}\x1b[0m", e);
+                std::process::exit(1);
+            }
+            std::process::exit(0);
+        }
+        Some(Command::BenchDisk { directory }) => {
+            let result = match downloader::validate_and_prepare_directory(directory).await {
+                Ok(dir) => bench::run(&dir, config.download.concurrent).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                eprintln!("\x1b[31m✗ error: {}\x1b[0m", e);
+                std::process::exit(1);
+            }
+            std::process::exit(0);
+        }
+        None => {}
+    }
+
     if let Err(e) = run(cli, config).await {
         eprintln!("\n\x1b[31m✗ error: {}\x1b[0m", e);
         std::process::exit(1);
@@ -82,35 +411,39 @@ async fn main() {
 async fn run(cli: Cli, config: config::Config) -> Result<()> {
     println!("osu! collect {} \n", env!("CARGO_PKG_VERSION"));
 
-    println!("Fetching collection...");
-    let collection_id = utils::parse_collection_id(&cli.collection)?;
-
-    let collection_client = collector::create_collection_client()?;
-    let collection = collector::fetch_collection(&collection_client, collection_id).await?;
-
-    collector::display_collection_info(&collection);
+    let inputs = match (&cli.collection, &cli.collection_list) {
+        (_, Some(list)) => batch::read_collection_list(list, &cli.options)?,
+        (Some(collection), None) => vec![batch::BatchEntry {
+            input: collection.clone(),
+            options: cli.options.clone(),
+        }],
+        (None, None) => return Err(AppError::other("No collection specified")),
+    };
 
     let directory = cli.directory.as_deref().unwrap_or(".");
     let base_dir = downloader::validate_and_prepare_directory(directory).await?;
 
-    let collection_folder_name = collection::generate_collection_folder_name(&collection);
-    let output_dir = base_dir.join(&collection_folder_name);
+    if downloader::is_osu_songs_folder(&base_dir) {
+        println!("\x1b[33m⚠\x1b[0m Warning: '{}' is inside an osu! Songs folder.", base_dir.display());
+        println!("  osu! does not import .osz files placed in Songs subfolders, download them");
+        println!("  somewhere else and open them with osu! (or drag them onto the game) instead.");
 
-    tokio::fs::create_dir_all(&output_dir).await?;
-
-    println!("\nCollection folder: {}", collection_folder_name);
-    println!("Downloading to: {}\n", output_dir.display());
+        if !cli.yes && !utils::confirm("Download here anyway? (y/N): ")? {
+            return Err(AppError::other("Download cancelled"));
+        }
+        println!();
+    }
 
-    let download_client = downloader::create_download_client()?;
+    let skip_existing = config.download.skip_existing || cli.skip_existing;
+    let interactive = std::io::stdin().is_terminal();
+    if !interactive && !skip_existing && !cli.yes {
+        println!(
+            "stdin is not a terminal, existing files will be handled with the default action: {} (download.file_exists_default)\n",
+            config.download.file_exists_default
+        );
+    }
 
-    let total_beatmaps = collection.beatmapsets.len();
-    let pb = ProgressBar::new(total_beatmaps as u64);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("{msg}\n{bar:40.cyan/blue} {pos}/{len} ({percent}%)")
-            .unwrap()
-            .progress_chars("█▓░"),
-    );
+    let progress_mode = cli.progress.resolve();
 
     let shutdown = Arc::new(AtomicBool::new(false));
     let shutdown_clone = shutdown.clone();
@@ -121,32 +454,431 @@ async fn run(cli: Cli, config: config::Config) -> Result<()> {
         }
     });
 
-    let concurrent = config.download.concurrent as usize;
-    let skip_existing = config.download.skip_existing || cli.skip_existing;
+    let profile = profile::Profile::new(cli.profile);
+    let download_client = downloader::create_download_client(&profile)?;
+    let sink = sink::create_sink(&config.output, &download_client)?;
+
+    let ctx = RunContext {
+        cli: &cli,
+        config: &config,
+        base_dir,
+        collection_client: collector::create_collection_client()?,
+        download_client,
+        mirrors: config.mirrors(),
+        preferred_mirror: Arc::new(AtomicUsize::new(0)),
+        archives: config.archives(),
+        limits: mirror::RateLimits::new(config.download.mirror_limits && !cli.ignore_mirror_limits),
+        sink,
+        ui: progress::create_ui(progress_mode),
+        progress: progress_mode,
+        slots: Arc::new(Semaphore::new(config.download.concurrent as usize)),
+        shutdown,
+        profile,
+        queues: cli.profile.then(Default::default),
+        failure_burst: notify::FailureBurst::new(config.notifications.failure_threshold),
+        interactive,
+        batch: inputs.len() > 1,
+        #[cfg(windows)]
+        taskbar: windows_init::TaskbarProgress::new(),
+        #[cfg(windows)]
+        taskbar_progress: Default::default(),
+    };
+
+    #[cfg(windows)]
+    if let Some(taskbar) = &ctx.taskbar {
+        taskbar.set_state(windows_init::TaskbarState::Normal);
+    }
+
+    if progress_mode == progress::ProgressMode::Bar && interactive {
+        picker::MirrorPicker {
+            ui: ctx.ui.clone(),
+            mirrors: ctx.mirrors.clone(),
+            templates: std::iter::once(&config.mirror.url).chain(&config.mirror.fallbacks).cloned().collect(),
+            preferred: ctx.preferred_mirror.clone(),
+        }.spawn();
+        println!("Type m and Enter during the downloads to switch the mirror\n");
+    }
+
+    let notifications = config.notifications.enabled;
+
+    if !ctx.batch {
+        let result = run_collection(&ctx, &inputs[0]).await;
+        journal::append(&[journal_entry(&cli.tag, &inputs[0].input, result.as_ref())]);
+        let report = result?;
+
+        ctx.profile.print();
+        println!();
+
+        if notifications {
+            notify::send(&format!("osu-collect: {}", report.name), &report.notification_body()).await;
+        }
+
+        if cli.open_failed {
+            open_failed_pages(std::slice::from_ref(&report));
+        }
+
+        print_final_message(&[report]);
+        return Ok(());
+    }
+
+    let parallel = config.download.parallel_collections as usize;
+    let ctx = &ctx;
+
+    let results = stream::iter(inputs.iter())
+        .map(|entry| async move {
+            let input = entry.input.as_str();
+            if ctx.shutdown.load(Ordering::SeqCst) {
+                return (input, None);
+            }
+
+            let result = run_collection(ctx, entry).await;
+            if let Err(e) = &result {
+                ctx.ui.suspend(|| println!("\x1b[31m✗ error ({}): {}\x1b[0m\n", input, e));
+            }
+            (input, Some(result))
+        })
+        .buffer_unordered(parallel)
+        .collect::<Vec<_>>()
+        .await;
+
+    let entries: Vec<journal::Entry> = results.iter()
+        .filter_map(|(input, result)| Some(journal_entry(&cli.tag, input, result.as_ref()?.as_ref())))
+        .collect();
+    journal::append(&entries);
+
+    println!("\n================================");
+    println!("Batch summary:");
+
+    let mut reports = Vec::new();
+    for (input, result) in results {
+        match result {
+            Some(Ok(report)) => {
+                println!(
+                    "  {}: downloaded {}, skipped {}, failed {}{}",
+                    report.name,
+                    report.downloaded,
+                    report.skipped,
+                    report.failed,
+                    if report.aborted { " (interrupted)" } else { "" },
+                );
+                reports.push(report);
+            }
+            Some(Err(e)) => println!("  \x1b[31m✗\x1b[0m {}: {}", input, e),
+            None => println!("  \x1b[33m⚠\x1b[0m {}: not started", input),
+        }
+    }
+
+    ctx.profile.print();
+    println!();
+
+    let errors = inputs.len() - reports.len();
+
+    if notifications {
+        let downloaded: u32 = reports.iter().map(|r| r.downloaded as u32).sum();
+        let failed: u32 = reports.iter().map(|r| r.failed as u32).sum();
+        notify::send(
+            "osu-collect: batch finished",
+            &format!(
+                "{} collections, downloaded: {}, failed: {}, errors: {}",
+                inputs.len(),
+                downloaded,
+                failed,
+                errors,
+            ),
+        ).await;
+    }
+
+    if cli.open_failed {
+        open_failed_pages(&reports);
+    }
+
+    if errors > 0 && !reports.iter().any(|r| r.aborted) {
+        println!("\x1b[33mCompleted with errors.\x1b[0m");
+    } else {
+        print_final_message(&reports);
+    }
+
+    Ok(())
+}
+
+/// Fetch a single collection and download its beatmapsets
+async fn run_collection(ctx: &RunContext<'_>, entry: &batch::BatchEntry) -> Result<CollectionReport> {
+    let cli = ctx.cli;
+    let ui = &ctx.ui;
+    let input = entry.input.as_str();
+    let options = &entry.options;
+
+    let started = std::time::Instant::now();
+    let source = utils::parse_source(input)?;
+    let report_source: Box<str> = source.to_string().into_boxed_str();
+
+    let fetch_started = ctx.profile.start();
+    let mut collection = match &source {
+        utils::Source::Collection(id) => {
+            ui.suspend(|| println!("Fetching collection {}...", id));
+            collector::fetch_collection(&ctx.collection_client, *id).await?
+        }
+        utils::Source::Tournament(id) => {
+            ui.suspend(|| println!("Fetching tournament {}...", id));
+            collector::fetch_tournament(&ctx.collection_client, *id).await?
+        }
+        utils::Source::Custom { name, id } => {
+            let source = ctx.config.sources.iter()
+                .find(|source| source.name == *name)
+                .ok_or_else(|| AppError::invalid_url_dynamic(
+                    format!("Unknown source '{}', define it under [[sources]] in the config", name).into_boxed_str()
+                ))?;
+            ui.suspend(|| println!("Fetching {} list {}...", name, id));
+            collector::fetch_custom(&ctx.collection_client, source, *id).await?
+        }
+    };
+    // Collections only list IDs and checksums, names, star ratings and status come separately
+    if let utils::Source::Collection(id) = &source
+        && options.needs_metadata()
+    {
+        let metadata = collection_metadata(ctx, *id, &collection).await
+            .map_err(|e| AppError::api_dynamic(
+                format!("Failed to fetch beatmap metadata needed by the selected options: {}", e).into_boxed_str()
+            ))?;
+        collection.apply_metadata(&metadata);
+    }
+    ctx.profile.record(profile::Phase::Fetch, fetch_started);
+
+    let layout = options.layout.unwrap_or_default();
+    if layout != collection::Layout::Flat {
+        let unknown = collection.beatmapsets.iter()
+            .filter(|beatmapset| layout.metadata_folder(beatmapset).is_none())
+            .count();
+        if unknown > 0 && unknown == collection.beatmapsets.len() {
+            return Err(AppError::other(
+                "No beatmapset has the artist, status or star rating --layout sorts by, use --layout flat"
+            ));
+        }
+        if unknown > 0 {
+            ui.suspend(|| println!(
+                "\x1b[33m⚠\x1b[0m {} beatmapsets lack the metadata --layout sorts by and go to Unknown/", unknown
+            ));
+        }
+    }
+
+    let collection_folder_name = collection::generate_collection_folder_name(&collection);
+    let output_dir = collection::find_collection_folder(&ctx.base_dir, &collection_folder_name);
+    let collection_folder_name = output_dir.file_name()
+        .and_then(|name| name.to_str())
+        .map_or(collection_folder_name.clone(), str::to_string);
+
+    // A folder this collection was downloaded into before is just resumed
+    if !cli.yes
+        && !cli.allow_nonempty
+        && !collection::is_previous_download(&output_dir, &collection)
+        && let Some(summary) = collection::summarize_folder(&output_dir)
+    {
+        confirm_existing_folder(ctx, &output_dir, &summary)?;
+    }
+
+    tokio::fs::create_dir_all(&output_dir).await?;
+
+    // Saved with the metadata, so `verify` puts repaired files back in the same place
+    for beatmapset in &mut collection.beatmapsets {
+        beatmapset.subfolder = layout.subfolder(beatmapset).map(String::into_boxed_str);
+    }
+
+    if let Err(e) = collection::save_collection_metadata(&collection, &output_dir) {
+        ui.suspend(|| println!("\x1b[33m⚠\x1b[0m Warning: {}", e));
+    }
+
+    let mut collection_state = state::CollectionState::load(&output_dir);
+
+    ui.suspend(|| -> Result<()> {
+        collector::display_collection_info(&collection);
+
+        if let Some(date) = &options.added_after {
+            let total = collection.beatmapsets.len();
+            match collector::retain_added_after(&mut collection, date) {
+                Some(undated) => {
+                    println!(
+                        "Added on or after {}: {} of {} beatmapsets",
+                        date,
+                        collection.beatmapsets.len(),
+                        total
+                    );
+                    if undated > 0 {
+                        println!("\x1b[33m⚠\x1b[0m {} beatmapsets have no date-added metadata and were excluded", undated);
+                    }
+                }
+                None => println!(
+                    "\x1b[33m⚠\x1b[0m Warning: the source has no date-added metadata for this collection, \
+                     --added-after can't filter it and every beatmapset is downloaded"
+                ),
+            }
+        }
+
+        if !options.diff_name_contains.is_empty() || !options.exclude_diff_name.is_empty() {
+            let (dropped, unnamed) =
+                collector::retain_diff_names(&mut collection, &options.diff_name_contains, &options.exclude_diff_name)
+                    .ok_or(AppError::other(
+                        "No difficulty names available for this collection, --diff-name-contains and \
+                         --exclude-diff-name can't be applied"
+                    ))?;
+            println!("Difficulty name filters: {} difficulties left out of collection.db", dropped);
+            if unnamed > 0 && !options.diff_name_contains.is_empty() {
+                println!("\x1b[33m⚠\x1b[0m {} difficulties have no name metadata and were left out", unnamed);
+            } else if unnamed > 0 {
+                println!("\x1b[33m⚠\x1b[0m {} difficulties have no name metadata and were kept unchecked", unnamed);
+            }
+        }
+
+        if !options.only_diffs.is_empty() {
+            let (dropped, skipped) = collector::retain_only_diffs(&mut collection, &options.only_diffs)
+                .ok_or(AppError::other(
+                    "No difficulty modes or names available for this collection, --only-diffs can't be applied"
+                ))?;
+            println!(
+                "Difficulty filter: {} difficulties left out of collection.db, {} beatmapsets without a matching difficulty skipped",
+                dropped, skipped
+            );
+            if collection.beatmapsets.is_empty() {
+                return Err(AppError::other("No difficulty matches --only-diffs, nothing to download"));
+            }
+        }
 
-    let results = stream::iter(collection.beatmapsets.iter())
+        if options.dedupe_titles && !collection.is_tournament() {
+            let (duplicates, untitled) = collector::find_duplicate_titles(&collection);
+            if untitled == collection.beatmapsets.len() {
+                println!("\x1b[33m⚠\x1b[0m Warning: no beatmapset has artist and title metadata, --dedupe-titles can't compare them");
+            } else if untitled > 0 {
+                println!("\x1b[33m⚠\x1b[0m {} beatmapsets have no artist or title metadata and weren't compared", untitled);
+            }
+            if !duplicates.is_empty() {
+                println!("\x1b[33m⚠\x1b[0m {} titles appear under several beatmapset IDs:", duplicates.len());
+                for group in &duplicates {
+                    let name = collection.beatmapsets.iter()
+                        .find(|set| set.id == group[0])
+                        .and_then(|set| set.display_name())
+                        .unwrap_or_default();
+                    let ids: Vec<String> = group.iter().map(u32::to_string).collect();
+                    println!("  - {} ({})", name, ids.join(", "));
+                }
+
+                let dropped = collector::retain_best_duplicates(&mut collection, &duplicates);
+                println!("Keeping the highest-status set of each, {} duplicates excluded", dropped);
+            }
+        }
+
+        println!("\nCollection folder: {}", collection_folder_name);
+        println!("Downloading to: {}\n", output_dir.display());
+        Ok(())
+    })?;
+
+    let mut manifest = manifest::Manifest::load(&output_dir).unwrap_or_else(|e| {
+        ui.suspend(|| println!("\x1b[33m⚠\x1b[0m Warning: {}, starting a new manifest", e));
+        manifest::Manifest::default()
+    });
+
+    let mut pending: Vec<&collector::Beatmapset> = collection.beatmapsets.iter()
+        .filter(|beatmapset| {
+            options.recheck_unavailable || !collection_state.unavailable.contains(&beatmapset.id)
+        })
+        .collect();
+
+    // Only the download order changes, collection.db keeps the collection order
+    if ctx.config.download.order == options::DownloadOrder::Shuffled {
+        utils::shuffle(&mut pending);
+    }
+
+    let known_unavailable = collection.beatmapsets.len() - pending.len();
+    if known_unavailable > 0 {
+        ui.suspend(|| println!(
+            "Skipping {} beatmapsets not found on any mirror previously (use --recheck-unavailable to retry)\n",
+            known_unavailable
+        ));
+    }
+
+    // The manifest remembers files stored in another sink, saving a lookup per set
+    let mut already_stored = 0;
+    if !ctx.sink.is_local() {
+        pending.retain(|beatmapset| {
+            let stored = manifest.files.get(&beatmapset.id).is_some_and(|entry| entry.sink == ctx.sink.kind());
+            already_stored += stored as u16;
+            !stored
+        });
+        if already_stored > 0 {
+            ui.suspend(|| println!("Skipping {} beatmapsets already stored in the output sink\n", already_stored));
+        }
+    }
+
+    let total_beatmaps = pending.len();
+    let pb = progress::add_bar(ui, ctx.progress, total_beatmaps as u64, &collection_folder_name, ctx.queues.clone());
+    if ctx.batch {
+        pb.set_message(collection_folder_name.clone());
+    }
+
+    #[cfg(windows)]
+    ctx.taskbar_progress.1.fetch_add(total_beatmaps as u64, Ordering::Relaxed);
+
+    let notifications = ctx.config.notifications.enabled;
+    let concurrent = ctx.config.download.concurrent as usize;
+
+    let download_ctx = downloader::DownloadContext {
+        client: &ctx.download_client,
+        mirrors: &ctx.mirrors,
+        preferred_mirror: &ctx.preferred_mirror,
+        limits: &ctx.limits,
+        archives: &ctx.archives,
+        output_dir: &output_dir,
+        sink: &*ctx.sink,
+        skip_existing: ctx.config.download.skip_existing || cli.skip_existing,
+        auto_overwrite: cli.yes,
+        interactive: ctx.interactive,
+        file_exists_default: ctx.config.download.file_exists_default,
+        extension: &ctx.config.download.extension,
+        hash: ctx.config.manifest.hash,
+        deadline: ctx.config.download.deadline(),
+        file_mode: ctx.config.download.file_mode()?,
+        metadata_collection: ctx.config.download.metadata_attributes.then_some(&*report_source),
+        owner: ctx.config.download.owner()?,
+        shutdown: ctx.shutdown.clone(),
+        profile: &ctx.profile,
+    };
+    let download_ctx = &download_ctx;
+
+    // Cleared again once collection.db and the manifest are both written
+    collection_state.incomplete = true;
+    if let Err(e) = collection_state.save(&output_dir) {
+        ui.suspend(|| println!("\x1b[33m⚠\x1b[0m Warning: {}", e));
+    }
+
+    let queues = ctx.queues.as_deref();
+    if let Some(queues) = queues {
+        queues.queued.fetch_add(total_beatmaps, Ordering::Relaxed);
+    }
+
+    let mut downloads = stream::iter(pending)
         .map(|beatmapset| {
-            let client = download_client.clone();
-            let mirror_url = config.mirror.url.to_string();
-            let output_dir = output_dir.clone();
             let beatmapset_id = beatmapset.id;
             let pb = pb.clone();
-            let shutdown = shutdown.clone();
+            let shutdown = ctx.shutdown.clone();
+            if let Some(queues) = queues {
+                progress::QueueDepths::advance(&queues.queued, &queues.waiting);
+            }
 
             async move {
+                let _slot = ctx.slots.acquire().await;
+                if let Some(queues) = queues {
+                    progress::QueueDepths::advance(&queues.waiting, &queues.in_flight);
+                }
+
                 if shutdown.load(Ordering::SeqCst) {
+                    if let Some(queues) = queues {
+                        queues.in_flight.fetch_sub(1, Ordering::Relaxed);
+                    }
                     return (beatmapset_id, downloader::DownloadResult::Aborted);
                 }
 
-                let result = downloader::download_beatmap(
-                    &client,
-                    beatmapset_id,
-                    &mirror_url,
-                    &output_dir,
-                    skip_existing,
-                    cli.yes,
-                    shutdown.clone(),
-                ).await;
+                let result = downloader::download_beatmap(download_ctx, beatmapset).await;
+                if let Some(queues) = queues {
+                    queues.in_flight.fetch_sub(1, Ordering::Relaxed);
+                }
 
                 let result = result.unwrap_or_else(|e| {
                     downloader::DownloadResult::FailedDynamic(
@@ -155,86 +887,569 @@ async fn run(cli: Cli, config: config::Config) -> Result<()> {
                 });
 
                 pb.inc(1);
+
+                let failed = matches!(
+                    result,
+                    downloader::DownloadResult::Failed(_) | downloader::DownloadResult::FailedDynamic(_)
+                );
+                if let Some(count) = ctx.failure_burst.record(failed)
+                    && notifications
+                {
+                    notify::send(
+                        "osu-collect: downloads failing",
+                        &format!("{} consecutive failures — mirror likely down", count),
+                    ).await;
+                }
+
+                #[cfg(windows)]
+                if let Some(taskbar) = &ctx.taskbar {
+                    let done = ctx.taskbar_progress.0.fetch_add(1, Ordering::Relaxed) + 1;
+                    taskbar.set_value(done, ctx.taskbar_progress.1.load(Ordering::Relaxed));
+                    match result {
+                        downloader::DownloadResult::Failed(_)
+                        | downloader::DownloadResult::FailedDynamic(_)
+                        | downloader::DownloadResult::NotFound => {
+                            taskbar.set_state(windows_init::TaskbarState::Error);
+                        }
+                        downloader::DownloadResult::Aborted => {
+                            taskbar.set_state(windows_init::TaskbarState::Paused);
+                        }
+                        _ => {}
+                    }
+                }
+
                 (beatmapset_id, result)
             }
         })
-        .buffer_unordered(concurrent)
-        .collect::<Vec<_>>()
-        .await;
+        .buffer_unordered(concurrent);
+
+    // State and manifest are updated as downloads finish and saved every
+    // `checkpoint_interval` results, so a crash only loses the last interval
+    let checkpoint_interval = ctx.config.download.checkpoint_interval as usize;
+    let hash_algorithm = ctx.config.manifest.hash;
+    let mut results = Vec::with_capacity(total_beatmaps);
+    let mut completed = 0;
+    let mut bytes = 0;
+    while let Some((beatmapset_id, result)) = downloads.next().await {
+        match &result {
+            downloader::DownloadResult::Success(filename, hash, size) => {
+                completed += 1;
+                bytes += size;
+                collection_state.unavailable.remove(&beatmapset_id);
+                collection_state.needs_attention.remove(&beatmapset_id);
+                if !ctx.sink.is_local() {
+                    manifest.record_stored(beatmapset_id, filename, *size, hash_algorithm, hash, ctx.sink.kind());
+                } else if let Err(e) = manifest.record_hashed(&output_dir, beatmapset_id, filename, hash_algorithm, hash) {
+                    ui.suspend(|| println!(
+                        "\x1b[33m⚠\x1b[0m Warning: failed to record {} in manifest: {}", filename, e
+                    ));
+                }
+            }
+            downloader::DownloadResult::Skipped(_) if !ctx.sink.is_local() => {
+                // Files in other sinks were recorded when they were stored
+                completed += 1;
+                collection_state.unavailable.remove(&beatmapset_id);
+                collection_state.needs_attention.remove(&beatmapset_id);
+            }
+            downloader::DownloadResult::Skipped(filename)
+            | downloader::DownloadResult::NeedsAttention(filename) => {
+                completed += 1;
+                collection_state.unavailable.remove(&beatmapset_id);
+                if matches!(result, downloader::DownloadResult::NeedsAttention(_)) {
+                    collection_state.needs_attention.insert(beatmapset_id, filename.clone());
+                } else {
+                    collection_state.needs_attention.remove(&beatmapset_id);
+                }
+                // Existing files are only hashed here, when they changed since they were recorded
+                let hash_started = ctx.profile.start();
+                if let Err(e) = manifest.record(&output_dir, beatmapset_id, filename, hash_algorithm) {
+                    ui.suspend(|| println!(
+                        "\x1b[33m⚠\x1b[0m Warning: failed to record {} in manifest: {}", filename, e
+                    ));
+                }
+                ctx.profile.record(profile::Phase::Hash, hash_started);
+            }
+            downloader::DownloadResult::NotFound => {
+                // Skipped as known unavailable from now on, so nothing left to do
+                completed += 1;
+                collection_state.unavailable.insert(beatmapset_id);
+                collection_state.needs_attention.remove(&beatmapset_id);
+            }
+            _ => {}
+        }
+
+        results.push((beatmapset_id, result));
+
+        if checkpoint_interval > 0 && results.len() % checkpoint_interval == 0 {
+            if let Err(e) = manifest.save(&output_dir) {
+                ui.suspend(|| println!("\x1b[33m⚠\x1b[0m Warning: checkpoint failed: {}", e));
+            }
+            if let Err(e) = collection_state.save(&output_dir) {
+                ui.suspend(|| println!("\x1b[33m⚠\x1b[0m Warning: checkpoint failed: {}", e));
+            }
+        }
+    }
+
+    drop(downloads);
+
+    // Names for the failure list, when no option needed the metadata before
+    let failed_unnamed = results.iter().any(|(beatmapset_id, result)| {
+        matches!(
+            result,
+            downloader::DownloadResult::Failed(_)
+            | downloader::DownloadResult::FailedDynamic(_)
+            | downloader::DownloadResult::NotFound
+        ) && collection.beatmapsets.iter()
+            .find(|beatmapset| beatmapset.id == *beatmapset_id)
+            .is_some_and(|beatmapset| beatmapset.display_name().is_none())
+    });
+    if let utils::Source::Collection(id) = &source
+        && failed_unnamed
+        && !options.needs_metadata()
+    {
+        match collection_metadata(ctx, *id, &collection).await {
+            Ok(metadata) => {
+                collection.apply_metadata(&metadata);
+            }
+            Err(e) => ui.suspend(|| println!(
+                "\x1b[33m⚠\x1b[0m Warning: failed to fetch beatmap names for the failure list: {}", e
+            )),
+        }
+    }
 
     pb.finish_and_clear();
+    ui.remove(&pb);
 
-    let mut downloaded_count: u16 = 0;
-    let mut skipped_count: u16 = 0;
-    let mut failed_count: u16 = 0;
+    let mut report = CollectionReport {
+        name: collection.name.clone(),
+        source: report_source.clone(),
+        downloaded: 0,
+        skipped: already_stored,
+        failed: 0,
+        needs_attention: 0,
+        known_unavailable,
+        bytes,
+        duration: std::time::Duration::ZERO,
+        aborted: false,
+        remaining: total_beatmaps - completed,
+        failed_ids: Vec::new(),
+    };
     let mut failed_downloads: Vec<(u32, Box<str>)> = Vec::new();
-    let mut aborted = false;
+    let mut needs_attention: Vec<Box<str>> = Vec::new();
 
-    for (beatmapset_id, result) in results {
-        match result {
-            downloader::DownloadResult::Success(filename) => {
-                downloaded_count += 1;
-                println!("\x1b[32m✓\x1b[0m Downloaded: {}", filename);
+    ui.suspend(|| {
+        for (beatmapset_id, result) in results {
+            match result {
+                downloader::DownloadResult::Success(filename, ..) => {
+                    report.downloaded += 1;
+                    println!("\x1b[32m✓\x1b[0m Downloaded: {}", filename);
+                }
+                downloader::DownloadResult::Skipped(filename) => {
+                    report.skipped += 1;
+                    println!("\x1b[33m⚠\x1b[0m Skipped (existing): {}", filename);
+                }
+                downloader::DownloadResult::NeedsAttention(filename) => {
+                    report.needs_attention += 1;
+                    println!("\x1b[33m⚠\x1b[0m Skipped (existing, no prompt possible): {}", filename);
+                    needs_attention.push(filename);
+                }
+                downloader::DownloadResult::Failed(reason) => {
+                    report.failed += 1;
+                    failed_downloads.push((beatmapset_id, reason.into()));
+                    println!("\x1b[31m✗\x1b[0m Error downloading {}: {}", beatmapset_id, reason);
+                }
+                downloader::DownloadResult::FailedDynamic(reason) => {
+                    report.failed += 1;
+                    failed_downloads.push((beatmapset_id, reason.clone()));
+                    println!("\x1b[31m✗\x1b[0m Error downloading {}: {}", beatmapset_id, reason);
+                }
+                downloader::DownloadResult::NotFound => {
+                    report.failed += 1;
+                    failed_downloads.push((beatmapset_id, "Not found on any mirror (404)".into()));
+                    println!("\x1b[31m✗\x1b[0m Error downloading {}: Not found on any mirror (404)", beatmapset_id);
+                }
+                downloader::DownloadResult::Aborted => {
+                    report.aborted = true;
+                    println!("\x1b[33m⚠  Download process aborted by user\x1b[0m");
+                    break;
+                }
+            }
+        }
+
+        let manifest_saved = match manifest.save(&output_dir) {
+            Ok(()) => true,
+            Err(e) => {
+                println!("\x1b[33m⚠\x1b[0m Warning: {}", e);
+                false
             }
-            downloader::DownloadResult::Skipped(filename) => {
-                skipped_count += 1;
-                println!("\x1b[33m⚠\x1b[0m Skipped (existing): {}", filename);
+        };
+
+        if !report.aborted {
+            println!("\nCreating collection.db...");
+            let db_collection_name = format!("{}-{}", collection.name, collection.id);
+            match collection::create_collection_db(
+                &collection,
+                &db_collection_name,
+                &output_dir,
+                &ctx.config.collection_db,
+            ) {
+                Ok(()) => {
+                    println!("\x1b[32m✓\x1b[0m collection.db created successfully");
+                    collection_state.incomplete = !manifest_saved;
+
+                    if let Some(db_path) = &cli.merge_into {
+                        merge_into_db(ctx, &collection, &db_collection_name, db_path);
+                    }
+                }
+                Err(e) => {
+                    println!("\x1b[33m⚠\x1b[0m Warning: Failed to create collection.db: {}", e);
+                }
             }
-            downloader::DownloadResult::Failed(reason) => {
-                failed_count += 1;
-                failed_downloads.push((beatmapset_id, reason.into()));
-                println!("\x1b[31m✗\x1b[0m Error downloading {}: {}", beatmapset_id, reason);
+        }
+
+        if let Err(e) = collection_state.save(&output_dir) {
+            println!("\x1b[33m⚠\x1b[0m Warning: {}", e);
+        }
+
+        if !report.aborted {
+            for &format in &options.export {
+                match export::write_export(&collection, &manifest, &output_dir, format) {
+                    Ok(path) => println!("\x1b[32m✓\x1b[0m Exported {}", path.display()),
+                    Err(e) => println!("\x1b[33m⚠\x1b[0m Warning: {}", e),
+                }
             }
-            downloader::DownloadResult::FailedDynamic(reason) => {
-                failed_count += 1;
-                failed_downloads.push((beatmapset_id, reason.clone()));
-                println!("\x1b[31m✗\x1b[0m Error downloading {}: {}", beatmapset_id, reason);
+        }
+
+        println!("\n================================");
+        if ctx.batch {
+            println!("Summary ({}):", collection.name);
+        } else {
+            println!("Summary:");
+        }
+        println!("\x1b[32m✓\x1b[0m Downloaded: {}", report.downloaded);
+        println!("\x1b[33m⚠\x1b[0m Skipped (existing): {}", report.skipped);
+        println!("\x1b[31m✗\x1b[0m Failed: {}", report.failed);
+        if report.needs_attention > 0 {
+            println!("\x1b[33m⚠\x1b[0m Needs attention: {}", report.needs_attention);
+        }
+        if known_unavailable > 0 {
+            println!("\x1b[33m⚠\x1b[0m Known unavailable: {}", known_unavailable);
+        }
+        if report.unfinished() {
+            match report.remaining_bytes() {
+                Some(bytes) => println!(
+                    "\x1b[33m⚠\x1b[0m Remaining: {} (~{:.0} MB)",
+                    report.remaining,
+                    bytes as f64 / 1024.0 / 1024.0,
+                ),
+                None => println!("\x1b[33m⚠\x1b[0m Remaining: {}", report.remaining),
             }
-            downloader::DownloadResult::Aborted => {
-                aborted = true;
-                println!("\x1b[33m⚠  Download process aborted by user\x1b[0m");
-                break;
+        }
+
+        if !needs_attention.is_empty() {
+            println!("\nNeeds attention (existing files skipped by download.file_exists_default, no prompt could be shown):");
+            for filename in &needs_attention {
+                println!("  - {}", filename);
             }
+            println!("Re-run in a terminal to choose per file, or pass --yes / --skip-existing.");
+            println!("They are also listed under \"needs_attention\" in {}.", state::STATE_FILE_NAME);
+        }
+
+        print_failed_downloads(&collection, &failed_downloads);
+        report.failed_ids = failed_downloads.iter().map(|(id, _)| *id).collect();
+
+        if ctx.batch {
+            println!();
+        }
+    });
+
+    report.duration = started.elapsed();
+    Ok(report)
+}
+
+/// Preview merging a collection into an existing collection.db and write it once confirmed.
+/// Failures only warn, the collection folder itself is complete at this point.
+/// Metadata of a collection's beatmaps from the cache, fetched and cached when any is missing
+async fn collection_metadata(
+    ctx: &RunContext<'_>,
+    id: u32,
+    collection: &collector::Collection,
+) -> Result<Vec<collector::BeatmapMetadata>> {
+    let path = cache::cache_path();
+    let mut cache = path.as_deref().map(cache::MetadataCache::load).unwrap_or_default();
+    let now = cache::now();
+
+    if let Some(metadata) = cache.lookup(collection, now) {
+        ctx.ui.suspend(|| println!("Using cached beatmap metadata"));
+        return Ok(metadata);
+    }
+
+    ctx.ui.suspend(|| println!("Fetching beatmap metadata..."));
+    let metadata = collector::fetch_collection_metadata(&ctx.collection_client, id).await?;
+
+    if let Some(path) = &path {
+        cache.insert(collection, &metadata, now);
+        if let Err(e) = cache.save(path) {
+            ctx.ui.suspend(|| println!(
+                "[33m⚠[0m Warning: failed to write {}: {}", path.display(), e
+            ));
         }
     }
 
-    if !aborted {
-        println!("\nCreating collection.db...");
-        let db_collection_name = format!("{}-{}", collection.name, collection.id);
-        match collection::create_collection_db(&collection, &db_collection_name, &output_dir) {
-            Ok(()) => {
-                println!("\x1b[32m✓\x1b[0m collection.db created successfully");
+    Ok(metadata)
+}
+
+fn merge_into_db(
+    ctx: &RunContext<'_>,
+    collection: &collector::Collection,
+    collection_name: &str,
+    db_path: &std::path::Path,
+) {
+    let (list, preview) = match collection::prepare_merge(collection, collection_name, db_path, &ctx.config.collection_db) {
+        Ok(merge) => merge,
+        Err(e) => {
+            println!("\x1b[33m⚠\x1b[0m Warning: {}", e);
+            return;
+        }
+    };
+
+    println!("\nMerging into {}:", db_path.display());
+    for (name, count) in &preview.added {
+        println!("  \x1b[32m+\x1b[0m {} ({} beatmaps)", name, count);
+    }
+    for (name, before, after) in &preview.updated {
+        println!("  \x1b[33m~\x1b[0m {} ({} -> {} beatmaps)", name, before, after);
+    }
+    for (name, count) in &preview.removed {
+        println!("  \x1b[31m-\x1b[0m {} ({} beatmaps)", name, count);
+    }
+    println!(
+        "  collections: {} -> {}, beatmaps: {} -> {}",
+        preview.collections.0, preview.collections.1, preview.hashes.0, preview.hashes.1,
+    );
+
+    if !ctx.cli.yes {
+        if !ctx.interactive {
+            println!("\x1b[33m⚠\x1b[0m Not merged, stdin is not a terminal (pass --yes to merge without confirmation)");
+            return;
+        }
+
+        println!("Close osu! first, it overwrites collection.db when it exits.");
+        match utils::confirm("Merge? (y/N): ") {
+            Ok(true) => {}
+            Ok(false) => {
+                println!("Not merged");
+                return;
             }
             Err(e) => {
-                println!("\x1b[33m⚠\x1b[0m Warning: Failed to create collection.db: {}", e);
+                println!("\x1b[33m⚠\x1b[0m Warning: {}", e);
+                return;
             }
         }
     }
 
-    println!("\n================================");
-    println!("Summary:");
-    println!("\x1b[32m✓\x1b[0m Downloaded: {}", downloaded_count);
-    println!("\x1b[33m⚠\x1b[0m Skipped (existing): {}", skipped_count);
-    println!("\x1b[31m✗\x1b[0m Failed: {}", failed_count);
+    match backup::create(db_path, ctx.config.backups.keep) {
+        Ok(Some(backup)) => println!("Backed up to {}", backup.display()),
+        Ok(None) => {}
+        Err(e) => {
+            println!("\x1b[33m⚠\x1b[0m Warning: {}, not merged", e);
+            return;
+        }
+    }
+
+    match collection::write_collection_list(&list, db_path) {
+        Ok(()) => println!("\x1b[32m✓\x1b[0m Merged into {}", db_path.display()),
+        Err(e) => println!("\x1b[33m⚠\x1b[0m Warning: {}", e),
+    }
+}
+
+/// Ask before downloading into a folder that already holds other files
+fn confirm_existing_folder(
+    ctx: &RunContext<'_>,
+    output_dir: &std::path::Path,
+    summary: &collection::FolderSummary,
+) -> Result<()> {
+    ctx.ui.suspend(|| {
+        println!(
+            "\x1b[33m⚠\x1b[0m Warning: '{}' already contains {} files ({:.2} GB, last modified {})",
+            output_dir.display(),
+            summary.files,
+            summary.bytes as f64 / 1024.0 / 1024.0 / 1024.0,
+            collector::date_from_unix(summary.last_modified as i64),
+        );
+        println!("  It wasn't created for this collection, downloading would mix its files with this one.");
 
-    if !failed_downloads.is_empty() {
-        println!("\nFailed downloads:");
-        for (id, reason) in failed_downloads {
-            println!("  - {} ({})", id, reason);
+        if !ctx.interactive {
+            return Err(AppError::other(
+                "Output folder is not empty and stdin is not a terminal, pass --allow-nonempty to download into it anyway"
+            ));
         }
+
+        if !utils::confirm("Download into it anyway? (y/N): ")? {
+            return Err(AppError::other("Download cancelled"));
+        }
+        println!();
+        Ok(())
+    })
+}
+
+/// Print the failure list with beatmapset names and links to the osu! website
+fn print_failed_downloads(collection: &collector::Collection, failed_downloads: &[(u32, Box<str>)]) {
+    if failed_downloads.is_empty() {
+        return;
+    }
+
+    let hyperlinks = utils::supports_hyperlinks();
+    let mut unnamed = 0;
+
+    println!("\nFailed downloads:");
+    for (id, reason) in failed_downloads {
+        let name = collection.beatmapsets.iter()
+            .find(|beatmapset| beatmapset.id == *id)
+            .and_then(collector::Beatmapset::display_name);
+        unnamed += usize::from(name.is_none());
+
+        let label = match name {
+            Some(name) => format!("{} {}", id, name),
+            None => id.to_string(),
+        };
+
+        let url = utils::beatmapset_url(*id);
+        if hyperlinks {
+            println!("  - {} ({})", utils::hyperlink(&url, &label), reason);
+        } else {
+            println!("  - {} ({}) {}", label, reason, url);
+        }
+    }
+
+    if unnamed > 0 {
+        println!("\x1b[33m⚠\x1b[0m {} of these have no artist/title metadata and are listed by ID only", unnamed);
+    }
+}
+
+/// Open the osu! website pages of failed beatmapsets for downloading them by hand
+fn open_failed_pages(reports: &[CollectionReport]) {
+    let ids: Vec<u32> = reports.iter().flat_map(|r| &r.failed_ids).copied().collect();
+    if ids.is_empty() {
+        return;
     }
 
+    if ids.len() > MAX_OPENED_PAGES {
+        println!("Opening the first {} of {} failed beatmapsets in the browser...", MAX_OPENED_PAGES, ids.len());
+    } else {
+        println!("Opening {} failed beatmapsets in the browser...", ids.len());
+    }
+
+    for id in ids.into_iter().take(MAX_OPENED_PAGES) {
+        if let Err(e) = utils::open_in_browser(&utils::beatmapset_url(id)) {
+            println!("\x1b[33m⚠\x1b[0m Warning: failed to open the browser: {}", e);
+            break;
+        }
+    }
     println!();
+}
+
+fn print_final_message(reports: &[CollectionReport]) {
+    let remaining: usize = reports.iter().filter(|r| r.unfinished()).map(|r| r.remaining).sum();
+    if remaining > 0 {
+        let remaining_bytes: u64 = reports.iter()
+            .filter(|r| r.unfinished())
+            .filter_map(CollectionReport::remaining_bytes)
+            .sum();
+        if remaining_bytes > 0 {
+            println!(
+                "{} beatmapsets left (~{:.0} MB), resume with:",
+                remaining,
+                remaining_bytes as f64 / 1024.0 / 1024.0,
+            );
+        } else {
+            println!("{} beatmapsets left, resume with:", remaining);
+        }
+        println!("  {}\n", resume_command());
+    }
 
-    if aborted {
+    if reports.iter().any(|r| r.aborted) {
         println!("\x1b[33mDownload process was interrupted.\x1b[0m");
-    } else if failed_count == 0 && skipped_count == 0 {
+    } else if reports.iter().all(|r| {
+        r.failed == 0 && r.skipped == 0 && r.needs_attention == 0 && r.known_unavailable == 0
+    }) {
         println!("\x1b[32mDone! All beatmaps downloaded successfully.\x1b[0m");
-    } else if failed_count == 0 {
+    } else if reports.iter().all(|r| r.failed == 0) {
         println!("\x1b[32mDone! All available beatmaps downloaded.\x1b[0m");
     } else {
         println!("\x1b[33mCompleted with errors.\x1b[0m");
     }
+}
 
-    Ok(())
+/// This invocation without `--yes`, with `--skip-existing` so finished files aren't downloaded again
+fn resume_command() -> String {
+    let mut args: Vec<String> = std::env::args()
+        .filter(|arg| arg != "-y" && arg != "--yes")
+        .collect();
+    if !args.iter().any(|arg| arg == "--skip-existing") {
+        args.push("--skip-existing".into());
+    }
+
+    args.iter()
+        .map(|arg| utils::shell_quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Journal line for a collection, errors are recorded against the raw input
+fn journal_entry<'a>(
+    tags: &'a [String],
+    input: &'a str,
+    result: std::result::Result<&'a CollectionReport, &AppError>,
+) -> journal::Entry<'a> {
+    match result {
+        Ok(report) => journal::Entry {
+            source: &report.source,
+            downloaded: report.downloaded,
+            skipped: report.skipped + report.needs_attention,
+            failed: report.failed,
+            bytes: report.bytes,
+            duration: report.duration,
+            status: if report.aborted {
+                "interrupted"
+            } else if report.failed > 0 {
+                "failed"
+            } else {
+                "ok"
+            },
+            tags,
+        },
+        Err(_) => journal::Entry {
+            source: input.trim(),
+            downloaded: 0,
+            skipped: 0,
+            failed: 0,
+            bytes: 0,
+            duration: std::time::Duration::ZERO,
+            status: "error",
+            tags,
+        },
+    }
+}
+
+impl CollectionReport {
+    /// Whether the run stopped or failed with sets still left to download
+    fn unfinished(&self) -> bool {
+        self.remaining > 0 && (self.aborted || self.failed > 0)
+    }
+
+    /// Size of the remaining sets, estimated from the average file downloaded in this run
+    fn remaining_bytes(&self) -> Option<u64> {
+        (self.downloaded > 0).then(|| self.bytes / self.downloaded as u64 * self.remaining as u64)
+    }
+
+    fn notification_body(&self) -> String {
+        format!(
+            "Downloaded: {}, skipped: {}, failed: {}{}",
+            self.downloaded,
+            self.skipped + self.needs_attention,
+            self.failed,
+            if self.aborted { " (interrupted)" } else { "" },
+        )
+    }
 }
@@ -0,0 +1,324 @@
+use crate::error::{AppError, Result};
+use crate::options::{HashAlgorithm, SinkKind};
+use crate::utils::write_atomic;
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+const MANIFEST_VERSION: u32 = 1;
+
+/// Record of every downloaded file in a collection folder, used by `verify`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// Entries keyed by beatmapset ID
+    #[serde(default)]
+    pub files: BTreeMap<u32, ManifestEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ManifestEntry {
+    pub filename: Box<str>,
+    pub size: u64,
+    /// Modification time in seconds since the unix epoch
+    pub mtime: u64,
+    /// Algorithm `hash` was computed with
+    pub algorithm: HashAlgorithm,
+    /// Content hash as lowercase hex
+    pub hash: Box<str>,
+    /// Output sink the file is stored in
+    pub sink: SinkKind,
+}
+
+/// Incremental hasher, fed while a file is streamed to disk so new downloads don't
+/// need a second read pass
+pub enum Hasher {
+    Md5(md5::Md5),
+    Sha1(sha1::Sha1),
+    Sha256(sha2::Sha256),
+    Xxh3(Box<xxhash_rust::xxh3::Xxh3>),
+}
+
+impl Hasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Md5 => Hasher::Md5(md5::Md5::new()),
+            HashAlgorithm::Sha1 => Hasher::Sha1(sha1::Sha1::new()),
+            HashAlgorithm::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+            HashAlgorithm::Xxh3 => Hasher::Xxh3(Box::default()),
+        }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Hasher::Md5(hasher) => hasher.update(bytes),
+            Hasher::Sha1(hasher) => hasher.update(bytes),
+            Hasher::Sha256(hasher) => hasher.update(bytes),
+            Hasher::Xxh3(hasher) => hasher.update(bytes),
+        }
+    }
+
+    /// Digest as lowercase hex
+    pub fn finish(self) -> String {
+        let digest = match self {
+            Hasher::Md5(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha1(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            Hasher::Xxh3(hasher) => hasher.digest128().to_be_bytes().to_vec(),
+        };
+
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyStatus {
+    /// Hash was checked and matches
+    Ok,
+    /// Size and mtime match the manifest, hashing was skipped
+    Unchanged,
+    Missing,
+    /// Content differs from the record, with the reason
+    Corrupt(Box<str>),
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Manifest {
+            version: MANIFEST_VERSION,
+            files: BTreeMap::new(),
+        }
+    }
+}
+
+impl Manifest {
+    /// Load the manifest of a collection folder, a missing manifest is empty
+    pub fn load(output_dir: &Path) -> Result<Self> {
+        let path = output_dir.join(MANIFEST_FILE_NAME);
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+
+        Self::parse(&contents)
+    }
+
+    fn parse(contents: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(contents)?;
+        let version = value.get("version").and_then(serde_json::Value::as_u64);
+
+        if version != Some(MANIFEST_VERSION as u64) {
+            return Err(AppError::other_dynamic(
+                format!(
+                    "Unsupported manifest version {} (expected {})",
+                    version.map_or("none".to_string(), |version| version.to_string()),
+                    MANIFEST_VERSION
+                ).into_boxed_str()
+            ));
+        }
+
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Save the manifest into the collection folder
+    pub fn save(&self, output_dir: &Path) -> Result<()> {
+        let contents = serde_json::to_string_pretty(self)?;
+        write_atomic(&output_dir.join(MANIFEST_FILE_NAME), contents).map_err(|e| {
+            AppError::other_dynamic(
+                format!("Failed to write {}: {}", MANIFEST_FILE_NAME, e).into_boxed_str()
+            )
+        })
+    }
+
+    /// Record a file, only re-hashing it when its size or mtime changed
+    pub fn record(
+        &mut self,
+        output_dir: &Path,
+        beatmapset_id: u32,
+        filename: &str,
+        algorithm: HashAlgorithm,
+    ) -> Result<()> {
+        let path = output_dir.join(filename);
+        let (size, mtime) = file_stat(&path)?;
+
+        if let Some(entry) = self.files.get(&beatmapset_id)
+            && *entry.filename == *filename
+            && entry.size == size
+            && entry.mtime == mtime
+        {
+            return Ok(());
+        }
+
+        self.files.insert(beatmapset_id, ManifestEntry {
+            filename: filename.into(),
+            size,
+            mtime,
+            algorithm,
+            hash: hash_file(&path, algorithm)?.into_boxed_str(),
+            sink: SinkKind::Directory,
+        });
+
+        Ok(())
+    }
+
+    /// Record a freshly downloaded file with the hash computed while it was streamed
+    pub fn record_hashed(
+        &mut self,
+        output_dir: &Path,
+        beatmapset_id: u32,
+        filename: &str,
+        algorithm: HashAlgorithm,
+        hash: &str,
+    ) -> Result<()> {
+        let (size, mtime) = file_stat(&output_dir.join(filename))?;
+
+        self.files.insert(beatmapset_id, ManifestEntry {
+            filename: filename.into(),
+            size,
+            mtime,
+            algorithm,
+            hash: hash.into(),
+            sink: SinkKind::Directory,
+        });
+
+        Ok(())
+    }
+
+    /// Record a file stored in an archive or remote sink, which can't be stat'ed locally.
+    /// The mtime is the time it was stored.
+    pub fn record_stored(
+        &mut self,
+        beatmapset_id: u32,
+        filename: &str,
+        size: u64,
+        algorithm: HashAlgorithm,
+        hash: &str,
+        sink: SinkKind,
+    ) {
+        let mtime = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+
+        self.files.insert(beatmapset_id, ManifestEntry {
+            filename: filename.into(),
+            size,
+            mtime,
+            algorithm,
+            hash: hash.into(),
+            sink,
+        });
+    }
+
+    /// Check a single entry against the file on disk. With `deep` every file is re-hashed,
+    /// otherwise files whose size and mtime match the record are trusted.
+    pub fn verify_entry(&mut self, output_dir: &Path, beatmapset_id: u32, deep: bool) -> Result<VerifyStatus> {
+        let Some(entry) = self.files.get_mut(&beatmapset_id) else {
+            return Ok(VerifyStatus::Missing);
+        };
+
+        let path = output_dir.join(&*entry.filename);
+        let (size, mtime) = match file_stat(&path) {
+            Ok(stat) => stat,
+            Err(AppError::FileSystem(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(VerifyStatus::Missing);
+            }
+            Err(e) => return Err(e),
+        };
+
+        if !deep && entry.size == size && entry.mtime == mtime {
+            return Ok(VerifyStatus::Unchanged);
+        }
+
+        if entry.size != size {
+            return Ok(VerifyStatus::Corrupt(
+                format!("size mismatch (expected {} bytes, found {})", entry.size, size).into_boxed_str()
+            ));
+        }
+
+        let hash = hash_file(&path, entry.algorithm)?;
+        if *entry.hash != *hash {
+            return Ok(VerifyStatus::Corrupt(
+                format!("{} mismatch (expected {}, found {})", entry.algorithm, entry.hash, hash).into_boxed_str()
+            ));
+        }
+
+        // Content is intact, only the timestamp moved (e.g. copied to another disk)
+        entry.mtime = mtime;
+        Ok(VerifyStatus::Ok)
+    }
+}
+
+/// Size and modification time (seconds since the unix epoch) of a file
+fn file_stat(path: &Path) -> Result<(u64, u64)> {
+    let metadata = std::fs::metadata(path)?;
+    let mtime = metadata.modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    Ok((metadata.len(), mtime))
+}
+
+/// Hash of a file as lowercase hex
+pub fn hash_file(path: &Path, algorithm: HashAlgorithm) -> Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Hasher::new(algorithm);
+    let mut buffer = vec![0u8; 64 * 1024];
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_current_version() {
+        let manifest = Manifest::parse(r#"{
+            "version": 1,
+            "files": {
+                "42": { "filename": "42 a.osz", "size": 10, "mtime": 5, "algorithm": "sha256", "hash": "abcd", "sink": "tar" }
+            }
+        }"#).unwrap();
+        let entry = &manifest.files[&42];
+
+        assert_eq!(entry.algorithm, HashAlgorithm::Sha256);
+        assert_eq!(&*entry.hash, "abcd");
+        assert_eq!(entry.sink, SinkKind::Tar);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        assert!(Manifest::parse(r#"{ "files": {} }"#).is_err());
+        assert!(Manifest::parse(r#"{ "version": 2, "files": {} }"#).is_err());
+    }
+
+    #[test]
+    fn hashers_match_known_digests() {
+        let digest = |algorithm| {
+            let mut hasher = Hasher::new(algorithm);
+            hasher.update(b"abc");
+            hasher.finish()
+        };
+
+        assert_eq!(digest(HashAlgorithm::Md5), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(digest(HashAlgorithm::Sha1), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(
+            digest(HashAlgorithm::Sha256),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
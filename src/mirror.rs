@@ -0,0 +1,199 @@
+use crate::config::{MirrorConfig, MirrorStep};
+use crate::error::{AppError, Result};
+use crate::profile::{Phase, Profile};
+use crate::utils::{fill_template, json_path};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Requests per minute for the built-in mirror presets, enforced unless `--ignore-mirror-limits`
+/// is passed or `download.mirror_limits` is off. None of the operators publish a limit,
+/// these are conservative estimates.
+const PRESET_LIMITS: [(&str, u32); 3] = [
+    ("api.nerinyan.moe", 60),
+    ("catboy.best", 60),
+    ("api.chimu.moe", 30),
+];
+/// How often a request waiting for its slot checks for Ctrl+C
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Spaces out requests to mirrors with a built-in limit, shared by every download of a run
+pub struct RateLimits {
+    enabled: bool,
+    /// Earliest time the next request may be sent, per limited host
+    next_slot: Mutex<HashMap<&'static str, Instant>>,
+}
+
+impl RateLimits {
+    pub fn new(enabled: bool) -> Self {
+        RateLimits {
+            enabled,
+            next_slot: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Wait until a request to the URL is allowed, returns immediately for unlimited hosts.
+    /// Returns `false` when interrupted by Ctrl+C.
+    pub async fn wait(&self, url: &str, shutdown: &AtomicBool) -> bool {
+        if !self.enabled {
+            return true;
+        }
+
+        let Some((host, interval)) = preset_limit(url) else {
+            return true;
+        };
+
+        let slot = {
+            let mut next_slot = self.next_slot.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let slot = next_slot.get(host).map_or(now, |next| (*next).max(now));
+            next_slot.insert(host, slot + interval);
+            slot
+        };
+
+        while Instant::now() < slot {
+            if shutdown.load(Ordering::Acquire) {
+                return false;
+            }
+            tokio::time::sleep_until(slot.min(Instant::now() + SHUTDOWN_POLL_INTERVAL)).await;
+        }
+
+        !shutdown.load(Ordering::Acquire)
+    }
+}
+
+/// Host and minimum spacing between requests of a mirror preset
+fn preset_limit(url: &str) -> Option<(&'static str, Duration)> {
+    let url = url::Url::parse(url).ok()?;
+    let host = url.host_str()?;
+
+    PRESET_LIMITS.iter()
+        .find(|(preset, _)| host.eq_ignore_ascii_case(preset))
+        .map(|(preset, per_minute)| (*preset, Duration::from_secs(60) / *per_minute))
+}
+
+/// Build the final download request for a beatmapset, running any intermediate mirror steps first.
+/// Every request waits for its slot in `limits`, `None` means Ctrl+C interrupted the wait.
+pub async fn build_download_request(
+    client: &reqwest::Client,
+    mirror: &MirrorConfig,
+    beatmapset_id: u32,
+    limits: &RateLimits,
+    shutdown: &AtomicBool,
+    profile: &Profile,
+) -> Result<Option<reqwest::RequestBuilder>> {
+    let mut vars: Vec<(Box<str>, Box<str>)> = vec![("id".into(), beatmapset_id.to_string().into())];
+
+    for (index, step) in mirror.steps.iter().enumerate() {
+        let url = fill_template(&step.url, &vars);
+        if !limits.wait(&url, shutdown).await {
+            return Ok(None);
+        }
+
+        let step_started = profile.start();
+        let captured = run_step(client, step, &url, &vars).await.map_err(|e| {
+            AppError::api_dynamic(format!("Mirror step {} failed: {}", index + 1, e).into_boxed_str())
+        })?;
+        profile.record(Phase::Request, step_started);
+        vars.extend(captured);
+    }
+
+    let url = fill_template(&mirror.url, &vars);
+    if !limits.wait(&url, shutdown).await {
+        return Ok(None);
+    }
+
+    Ok(Some(apply_headers(client.get(url), &mirror.headers, &vars)))
+}
+
+/// Run a single intermediate request and capture the configured values from its JSON response
+async fn run_step(
+    client: &reqwest::Client,
+    step: &MirrorStep,
+    url: &str,
+    vars: &[(Box<str>, Box<str>)],
+) -> Result<Vec<(Box<str>, Box<str>)>> {
+    let method = parse_method(&step.method)?;
+
+    let mut request = apply_headers(client.request(method, url), &step.headers, vars);
+    if let Some(body) = &step.body {
+        request = request.body(fill_template(body, vars));
+    }
+
+    let response = request.send().await?;
+    let status = response.status();
+
+    if !status.is_success() {
+        return Err(AppError::api_dynamic(format!("HTTP {}", status).into_boxed_str()));
+    }
+
+    if step.extract.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let json: serde_json::Value = response.json().await?;
+
+    step.extract
+        .iter()
+        .map(|(name, path)| {
+            let value = json_path(&json, path).ok_or_else(|| {
+                AppError::api_dynamic(format!("response is missing '{}'", path).into_boxed_str())
+            })?;
+
+            let value = match value {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+
+            Ok((name.clone(), value.into_boxed_str()))
+        })
+        .collect()
+}
+
+/// Parse the HTTP method of a mirror step
+pub fn parse_method(method: &str) -> Result<reqwest::Method> {
+    match method.to_ascii_uppercase().as_str() {
+        "GET" => Ok(reqwest::Method::GET),
+        "POST" => Ok(reqwest::Method::POST),
+        _ => Err(AppError::other_dynamic(
+            format!("Unsupported mirror step method: {}", method).into_boxed_str()
+        )),
+    }
+}
+
+#[inline]
+fn apply_headers(
+    request: reqwest::RequestBuilder,
+    headers: &BTreeMap<Box<str>, Box<str>>,
+    vars: &[(Box<str>, Box<str>)],
+) -> reqwest::RequestBuilder {
+    headers.iter().fold(request, |request, (name, value)| {
+        request.header(name.as_ref(), fill_template(value, vars))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preset_limit_matches_preset_hosts() {
+        assert_eq!(preset_limit("https://catboy.best/d/1"), Some(("catboy.best", Duration::from_secs(1))));
+        assert_eq!(preset_limit("https://API.chimu.moe/v1/download/1?n=1"), Some(("api.chimu.moe", Duration::from_secs(2))));
+        assert_eq!(preset_limit("https://mirror.example.com/d/1"), None);
+    }
+
+    #[tokio::test]
+    async fn disabled_limits_never_wait() {
+        let limits = RateLimits::new(false);
+        let shutdown = AtomicBool::new(false);
+        let started = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limits.wait("https://api.chimu.moe/v1/download/1", &shutdown).await);
+        }
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
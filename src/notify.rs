@@ -0,0 +1,45 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Tracks consecutive download failures and fires a single alert when a burst is detected
+pub struct FailureBurst {
+    threshold: u32,
+    consecutive: AtomicU32,
+    alerted: AtomicBool,
+}
+
+impl FailureBurst {
+    pub const fn new(threshold: u32) -> Self {
+        FailureBurst {
+            threshold,
+            consecutive: AtomicU32::new(0),
+            alerted: AtomicBool::new(false),
+        }
+    }
+
+    /// Record a download outcome, returns the burst size the first time the threshold is reached
+    pub fn record(&self, failed: bool) -> Option<u32> {
+        if !failed {
+            self.consecutive.store(0, Ordering::Relaxed);
+            return None;
+        }
+
+        let count = self.consecutive.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.threshold > 0 && count >= self.threshold && !self.alerted.swap(true, Ordering::Relaxed) {
+            return Some(count);
+        }
+
+        None
+    }
+}
+
+/// Show a desktop notification, errors are ignored since notifications are best-effort.
+/// Showing one is a blocking D-Bus / WinRT call, so it runs on the blocking thread pool.
+pub async fn send(summary: &str, body: &str) {
+    let mut notification = notify_rust::Notification::new();
+    notification
+        .appname("osu-collect")
+        .summary(summary)
+        .body(body);
+
+    let _ = tokio::task::spawn_blocking(move || notification.show().map(drop)).await;
+}
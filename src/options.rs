@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+
+/// What happens to a download whose file already exists
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileExistsAction {
+    #[default]
+    Skip,
+    Overwrite,
+    Abort,
+}
+
+impl std::fmt::Display for FileExistsAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            FileExistsAction::Skip => "skip",
+            FileExistsAction::Overwrite => "overwrite",
+            FileExistsAction::Abort => "abort",
+        })
+    }
+}
+
+/// Order beatmapsets of a collection are scheduled in
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadOrder {
+    /// Collection order
+    #[default]
+    Collection,
+    /// Random order, so users downloading the same collection don't request sets in lockstep
+    Shuffled,
+}
+
+/// Hash used for manifest entries, xxh3 is the fastest and sha256 suits archival
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    #[default]
+    Xxh3,
+}
+
+impl std::fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            HashAlgorithm::Md5 => "MD5",
+            HashAlgorithm::Sha1 => "SHA-1",
+            HashAlgorithm::Sha256 => "SHA-256",
+            HashAlgorithm::Xxh3 => "XXH3",
+        })
+    }
+}
+
+/// How beatmap hashes appearing more than once across the written entries are handled
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DedupePolicy {
+    /// Keep every occurrence
+    #[default]
+    KeepAll,
+    /// Keep only the first occurrence, in entry order
+    KeepFirst,
+}
+
+/// Where downloaded files end up, see `sink::OutputSink`
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SinkKind {
+    /// Files stay in the collection folder
+    #[default]
+    Directory,
+    /// Files are appended to `beatmaps.tar` in the collection folder
+    Tar,
+    /// Files are appended to `beatmaps.zip` in the collection folder
+    Zip,
+    /// Files are streamed to a WebDAV server
+    WebDav,
+}
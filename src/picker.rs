@@ -0,0 +1,86 @@
+use crate::config::{self, MirrorConfig};
+use crate::error::{AppError, Result};
+use crate::setup;
+use crate::utils;
+use indicatif::{MultiProgress, ProgressDrawTarget};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Mirror picker opened by typing `m` during a run with a progress bar. It probes the
+/// configured mirrors, switches the one tried first for every download that starts
+/// afterwards and saves the choice as the main mirror in the config.
+pub struct MirrorPicker {
+    pub ui: MultiProgress,
+    pub mirrors: Vec<MirrorConfig>,
+    /// URL templates of `mirrors` as written in the config
+    pub templates: Vec<Box<str>>,
+    /// Index into `mirrors` tried first, shared with every download of the run
+    pub preferred: Arc<AtomicUsize>,
+}
+
+impl MirrorPicker {
+    /// Listen for `m` on stdin for the rest of the run
+    pub fn spawn(self) {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        utils::route_stdin_lines(move |line| {
+            if line.trim().eq_ignore_ascii_case("m") {
+                let _ = sender.send(());
+            }
+        });
+
+        tokio::spawn(async move {
+            while receiver.recv().await.is_some() {
+                // Bars are hidden while the picker asks, redrawing would overwrite the input
+                let _ = self.ui.clear();
+                self.ui.set_draw_target(ProgressDrawTarget::hidden());
+
+                if let Err(e) = self.pick().await {
+                    println!("\x1b[33m⚠\x1b[0m Warning: {}", e);
+                }
+
+                println!();
+                self.ui.set_draw_target(ProgressDrawTarget::stderr());
+            }
+        });
+    }
+
+    async fn pick(&self) -> Result<()> {
+        let current = self.preferred.load(Ordering::Relaxed);
+
+        println!("\nMeasuring mirror latency, downloads keep running...");
+        let latencies = setup::probe_latencies(self.mirrors.iter().map(|mirror| &*mirror.url)).await?;
+
+        for (index, (mirror, latency)) in self.mirrors.iter().zip(&latencies).enumerate() {
+            let marker = if index == current { " (current)" } else { "" };
+            match latency {
+                Some(latency) => println!("  {}) {:>5} ms  {}{}", index + 1, latency.as_millis(), mirror.url, marker),
+                None => println!("  {}) {:>8}  {}{}", index + 1, "down", mirror.url, marker),
+            }
+        }
+
+        let count = self.mirrors.len();
+        let choice = tokio::task::spawn_blocking(move || setup::prompt_number("Mirror", count, Some(current)))
+            .await
+            .map_err(|e| AppError::other_dynamic(e.to_string().into_boxed_str()))??
+            .unwrap_or(current);
+
+        if choice == current {
+            println!("Keeping {}", self.mirrors[choice].url);
+            return Ok(());
+        }
+
+        self.preferred.store(choice, Ordering::Relaxed);
+        println!("\x1b[32m✓\x1b[0m Downloads starting from now use {} first", self.mirrors[choice].url);
+
+        let path = config::config_path()
+            .ok_or(AppError::other("Could not determine the config directory, the choice is kept for this run only"))?;
+        let mut saved = config::load_config()?;
+        saved.promote_mirror(&self.templates[choice]).map_err(|e| {
+            AppError::other_dynamic(format!("{}, the choice is kept for this run only", e).into_boxed_str())
+        })?;
+        config::save_config(&saved, &path)?;
+        println!("\x1b[32m✓\x1b[0m Saved as the main mirror in {}", path.display());
+
+        Ok(())
+    }
+}
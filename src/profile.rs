@@ -0,0 +1,163 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    /// Fetching collection metadata from osu!collector
+    Fetch,
+    /// Opening connections to mirrors (DNS, TCP and TLS), measured by `ConnectTimer`
+    Connect,
+    /// Mirror steps and download requests until the response headers arrived, connecting included
+    Request,
+    /// Receiving the response body from the mirror
+    Body,
+    /// Hashing received data for the manifest
+    Hash,
+    /// Creating, writing and flushing files
+    Disk,
+}
+
+const PHASES: [Phase; 6] = [Phase::Fetch, Phase::Connect, Phase::Request, Phase::Body, Phase::Hash, Phase::Disk];
+
+/// Time spent in each phase of a run, collected when `--profile` is passed
+#[derive(Debug, Default)]
+pub struct Profile {
+    enabled: bool,
+    /// Shared with the `ConnectTimer` of the download client
+    micros: Arc<[AtomicU64; 6]>,
+    downloads: AtomicU32,
+}
+
+impl Profile {
+    pub fn new(enabled: bool) -> Self {
+        Profile {
+            enabled,
+            ..Default::default()
+        }
+    }
+
+    /// Start timing a phase, returns `None` when profiling is disabled
+    #[inline]
+    pub fn start(&self) -> Option<Instant> {
+        self.enabled.then(Instant::now)
+    }
+
+    /// Add the time since `started` to a phase
+    #[inline]
+    pub fn record(&self, phase: Phase, started: Option<Instant>) {
+        if let Some(started) = started {
+            self.add(phase, started.elapsed());
+        }
+    }
+
+    #[inline]
+    pub fn add(&self, phase: Phase, elapsed: Duration) {
+        if self.enabled {
+            self.micros[phase as usize].fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        }
+    }
+
+    #[inline]
+    pub fn count_download(&self) {
+        if self.enabled {
+            self.downloads.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Connector layer timing new connections of a client, `None` when profiling is disabled
+    pub fn connect_timer(&self) -> Option<ConnectTimer> {
+        self.enabled.then(|| ConnectTimer { micros: self.micros.clone() })
+    }
+
+    /// Print the breakdown of time spent per phase
+    pub fn print(&self) {
+        if !self.enabled {
+            return;
+        }
+
+        let downloads = self.downloads.load(Ordering::Relaxed).max(1) as u64;
+        let connect = self.micros[Phase::Connect as usize].load(Ordering::Relaxed);
+
+        println!("\nProfile:");
+        for phase in PHASES {
+            let micros = self.micros[phase as usize].load(Ordering::Relaxed);
+            let (label, micros) = match phase {
+                Phase::Fetch => ("Collection fetch", micros),
+                Phase::Connect => ("Connect", micros),
+                // Requests on a new connection include connecting, the rest is server latency
+                Phase::Request => ("Time to first byte", micros.saturating_sub(connect)),
+                Phase::Body => ("Body transfer", micros),
+                Phase::Hash => ("Hashing", micros),
+                Phase::Disk => ("Disk writes", micros),
+            };
+
+            if phase == Phase::Fetch {
+                println!("  {:<18} {:>10.2}s", label, micros as f64 / 1_000_000.0);
+            } else {
+                println!(
+                    "  {:<18} {:>10.2}s  (avg {:.0} ms per download)",
+                    label,
+                    micros as f64 / 1_000_000.0,
+                    micros as f64 / downloads as f64 / 1000.0,
+                );
+            }
+        }
+
+        let body = self.micros[Phase::Body as usize].load(Ordering::Relaxed);
+        let disk = self.micros[Phase::Disk as usize].load(Ordering::Relaxed);
+        if disk > body {
+            println!("  \x1b[33m⚠\x1b[0m More time was spent writing to disk than receiving data, the disk is likely the bottleneck");
+        }
+    }
+}
+
+/// Connector layer adding the time spent opening each connection to `Phase::Connect`.
+/// Pooled connections are reused without connecting, so only new ones are counted.
+#[derive(Debug, Clone)]
+pub struct ConnectTimer {
+    micros: Arc<[AtomicU64; 6]>,
+}
+
+impl<S> tower_layer::Layer<S> for ConnectTimer {
+    type Service = TimedConnector<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TimedConnector { inner, micros: self.micros.clone() }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TimedConnector<S> {
+    inner: S,
+    micros: Arc<[AtomicU64; 6]>,
+}
+
+impl<S, R> tower_service::Service<R> for TimedConnector<S>
+where
+    S: tower_service::Service<R>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        let connecting = self.inner.call(request);
+        let micros = self.micros.clone();
+
+        Box::pin(async move {
+            let started = Instant::now();
+            let result = connecting.await;
+            micros[Phase::Connect as usize].fetch_add(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+            result
+        })
+    }
+}
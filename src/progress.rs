@@ -0,0 +1,134 @@
+use clap::ValueEnum;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const PLAIN_PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+const QUEUE_REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Beatmapsets in each stage of the download pipeline, shared by every collection of a run.
+/// Shown next to the progress with `--profile`: a long `waiting` queue means the mirror or
+/// disk is the bottleneck, an empty one with idle slots means the sets aren't coming in fast enough.
+#[derive(Debug, Default)]
+pub struct QueueDepths {
+    /// Fetched from the source but not scheduled yet
+    pub queued: AtomicUsize,
+    /// Scheduled and waiting for a download slot
+    pub waiting: AtomicUsize,
+    /// Holding a slot: requesting, receiving or writing
+    pub in_flight: AtomicUsize,
+}
+
+impl QueueDepths {
+    /// Move one set from one stage to the next
+    #[inline]
+    pub fn advance(from: &AtomicUsize, to: &AtomicUsize) {
+        from.fetch_sub(1, Ordering::Relaxed);
+        to.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "queued {}, waiting {}, in flight {}",
+            self.queued.load(Ordering::Relaxed),
+            self.waiting.load(Ordering::Relaxed),
+            self.in_flight.load(Ordering::Relaxed),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum ProgressMode {
+    /// Progress bar on terminals, plain lines otherwise
+    #[default]
+    Auto,
+    /// Interactive progress bar
+    Bar,
+    /// Periodic single-line summaries without control sequences (for docker logs)
+    Plain,
+}
+
+impl ProgressMode {
+    /// Resolve `auto` depending on whether stdout is a terminal
+    pub fn resolve(self) -> Self {
+        match self {
+            ProgressMode::Auto if std::io::stdout().is_terminal() => ProgressMode::Bar,
+            ProgressMode::Auto => ProgressMode::Plain,
+            mode => mode,
+        }
+    }
+}
+
+/// Create the container all progress bars of a run are drawn in
+pub fn create_ui(mode: ProgressMode) -> MultiProgress {
+    match mode {
+        ProgressMode::Plain => MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+        _ => MultiProgress::new(),
+    }
+}
+
+/// Add a progress bar for a collection, in plain mode its state is printed periodically instead.
+/// With `queues` the pipeline queue depths are shown alongside.
+pub fn add_bar(
+    ui: &MultiProgress,
+    mode: ProgressMode,
+    len: u64,
+    label: &str,
+    queues: Option<Arc<QueueDepths>>,
+) -> ProgressBar {
+    let pb = ui.add(ProgressBar::new(len));
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{msg}\n{bar:40.cyan/blue} {pos}/{len} ({percent}%) {prefix}")
+            .unwrap()
+            .progress_chars("█▓░"),
+    );
+
+    if mode == ProgressMode::Plain {
+        spawn_plain_reporter(pb.clone(), label.to_string(), queues);
+    } else if let Some(queues) = queues {
+        spawn_queue_display(pb.clone(), queues);
+    }
+
+    pb
+}
+
+fn spawn_queue_display(pb: ProgressBar, queues: Arc<QueueDepths>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(QUEUE_REFRESH_INTERVAL);
+
+        while !pb.is_finished() {
+            pb.set_prefix(queues.summary());
+            interval.tick().await;
+        }
+    });
+}
+
+fn spawn_plain_reporter(pb: ProgressBar, label: String, queues: Option<Arc<QueueDepths>>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PLAIN_PROGRESS_INTERVAL);
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+            if pb.is_finished() {
+                break;
+            }
+
+            let len = pb.length().unwrap_or(0);
+            let pos = pb.position();
+            let percent = (pos * 100).checked_div(len).unwrap_or(100);
+            println!(
+                "progress: {} {}/{} ({}%) elapsed {}s{}",
+                label,
+                pos,
+                len,
+                percent,
+                pb.elapsed().as_secs(),
+                queues.as_ref().map_or(String::new(), |queues| format!(" {}", queues.summary())),
+            );
+        }
+    });
+}
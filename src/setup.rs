@@ -0,0 +1,138 @@
+use crate::config::{self, Config};
+use crate::error::{AppError, Result};
+use crate::healthcheck::origin;
+use crate::utils;
+use std::io::{IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+/// Mirrors offered by the wizard, as in `config.toml.example`
+const MIRROR_PRESETS: [(&str, &str); 3] = [
+    ("nerinyan", "https://api.nerinyan.moe/d/{id}"),
+    ("catboy", "https://catboy.best/d/{id}"),
+    ("chimu", "https://api.chimu.moe/v1/download/{id}?n=1"),
+];
+/// Regions offered by the wizard and the preset expected to be closest to each
+/// (nerinyan is hosted in Asia, catboy in Europe and North America)
+const REGIONS: [(&str, usize); 4] = [
+    ("Europe", 1),
+    ("North America", 1),
+    ("Asia", 0),
+    ("Oceania", 0),
+];
+const PROBE_ATTEMPTS: u8 = 3;
+const PROBE_TIMEOUT_SECS: u64 = 5;
+
+/// Interactive first-run setup: ask for the region, preselect the mirror preset expected to be
+/// closest, confirm it with a quick probe and write the config with the others as fallbacks
+pub async fn run() -> Result<()> {
+    if !std::io::stdin().is_terminal() {
+        return Err(AppError::other("setup is interactive, run it in a terminal"));
+    }
+
+    let path = config::config_path()
+        .ok_or(AppError::other("Could not determine the config directory"))?;
+
+    if path.exists()
+        && !utils::confirm(&format!("{} already exists, overwrite it? (y/N): ", path.display()))?
+    {
+        return Err(AppError::other("Setup cancelled"));
+    }
+
+    println!("\nWhere are you downloading from?");
+    for (index, (region, _)) in REGIONS.iter().enumerate() {
+        println!("  {}) {}", index + 1, region);
+    }
+    let region = prompt_number("Region", REGIONS.len(), None)?;
+
+    println!("\nMeasuring mirror latency from this machine...");
+    let latencies = probe_latencies(MIRROR_PRESETS.iter().map(|(_, url)| *url)).await?;
+
+    for (index, ((name, url), latency)) in MIRROR_PRESETS.iter().zip(&latencies).enumerate() {
+        match latency {
+            Some(latency) => println!("  {}) {:<10} {:>5} ms  {}", index + 1, name, latency.as_millis(), url),
+            None => println!("  {}) {:<10} {:>8}  {}", index + 1, name, "down", url),
+        }
+    }
+
+    // Fastest reachable mirror, the first preset when none answered
+    let fastest = latencies.iter()
+        .enumerate()
+        .filter_map(|(index, latency)| Some((index, (*latency)?)))
+        .min_by_key(|(_, latency)| *latency)
+        .map_or(0, |(index, _)| index);
+
+    // The region's preset unless the probe found it down
+    let preselected = region
+        .map(|region| REGIONS[region].1)
+        .filter(|preset| latencies[*preset].is_some())
+        .unwrap_or(fastest);
+
+    let choice = prompt_number("\nMirror", MIRROR_PRESETS.len(), Some(preselected))?
+        .unwrap_or(preselected);
+
+    let mut config = Config::default();
+    config.mirror.url = MIRROR_PRESETS[choice].1.into();
+    config.mirror.fallbacks = MIRROR_PRESETS.iter()
+        .enumerate()
+        .filter(|(index, _)| *index != choice && latencies[*index].is_some())
+        .map(|(_, (_, url))| (*url).into())
+        .collect();
+
+    config::save_config(&config, &path)?;
+
+    println!("\n\x1b[32m✓\x1b[0m Wrote {} (mirror: {})", path.display(), MIRROR_PRESETS[choice].0);
+    println!("  See config.toml.example for every other option.");
+
+    Ok(())
+}
+
+/// Best of a few HEAD round trips to each mirror's host, `None` when unreachable
+pub async fn probe_latencies<'a>(urls: impl Iterator<Item = &'a str>) -> Result<Vec<Option<Duration>>> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(PROBE_TIMEOUT_SECS))
+        .build()?;
+
+    let probes = urls.map(|url| {
+        let client = &client;
+        async move {
+            let origin = origin(url)?;
+            let mut best: Option<Duration> = None;
+
+            for _ in 0..PROBE_ATTEMPTS {
+                let started = Instant::now();
+                if client.head(&origin).send().await.is_ok() {
+                    let elapsed = started.elapsed();
+                    best = Some(best.map_or(elapsed, |best| best.min(elapsed)));
+                }
+            }
+
+            best
+        }
+    });
+
+    Ok(futures_util::future::join_all(probes).await)
+}
+
+/// Ask for a number between 1 and `count`, returned zero-based. Enter picks `default`,
+/// or skips the question when there is none.
+pub fn prompt_number(label: &str, count: usize, default: Option<usize>) -> Result<Option<usize>> {
+    loop {
+        match default {
+            Some(default) => print!("{} [1-{}, Enter for {}]: ", label, count, default + 1),
+            None => print!("{} [1-{}, Enter to skip]: ", label, count),
+        }
+        std::io::stdout().flush()?;
+
+        let input = utils::read_line()?;
+        let input = input.trim();
+
+        if input.is_empty() {
+            return Ok(default);
+        }
+
+        match input.parse::<usize>() {
+            Ok(n) if (1..=count).contains(&n) => return Ok(Some(n - 1)),
+            _ => println!("Enter a number between 1 and {}", count),
+        }
+    }
+}
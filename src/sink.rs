@@ -0,0 +1,505 @@
+use crate::config::OutputConfig;
+use crate::error::{AppError, Result};
+use crate::options::SinkKind;
+use crate::zip;
+use futures_util::future::BoxFuture;
+use std::collections::{HashMap, HashSet};
+use std::io::{Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use url::Url;
+
+/// Size of the end-of-archive marker (two empty 512 byte blocks) of a tar file
+const TAR_TERMINATOR_SIZE: u64 = 1024;
+/// Chunks queued for a WebDAV upload before the download waits for the server
+const UPLOAD_QUEUE_CHUNKS: usize = 8;
+
+/// Where downloaded files end up. A download streams its body into the file returned by
+/// `create`, which only shows up in the sink once it is finished.
+pub trait OutputSink: Send + Sync {
+    fn kind(&self) -> SinkKind;
+
+    /// Whether `filename` is already stored. Names are relative to the collection folder and
+    /// may contain a layout subfolder.
+    fn contains<'a>(&'a self, output_dir: &'a Path, filename: &'a str) -> BoxFuture<'a, Result<bool>>;
+
+    /// Start storing a file
+    fn create<'a>(&'a self, output_dir: &'a Path, filename: &'a str) -> BoxFuture<'a, Result<Box<dyn SinkFile>>>;
+
+    /// Whether stored files are plain files in the collection folder
+    #[inline]
+    fn is_local(&self) -> bool {
+        self.kind() == SinkKind::Directory
+    }
+}
+
+/// A file being streamed into a sink
+pub trait SinkFile: Send {
+    fn write<'a>(&'a mut self, bytes: &'a [u8]) -> BoxFuture<'a, Result<()>>;
+
+    /// Complete the file in the sink
+    fn finish(self: Box<Self>) -> BoxFuture<'static, Result<()>>;
+
+    /// Throw away what was written, e.g. after a failed or cancelled transfer
+    fn abort(self: Box<Self>) -> BoxFuture<'static, ()>;
+}
+
+/// Sink configured with `output.sink`. S3 isn't offered: it needs signed (SigV4) multipart
+/// uploads, WebDAV gateways in front of a bucket (e.g. `rclone serve webdav`) cover it.
+pub fn create_sink(config: &OutputConfig, client: &reqwest::Client) -> Result<Arc<dyn OutputSink>> {
+    Ok(match config.sink {
+        SinkKind::Directory => Arc::new(DirectorySink),
+        SinkKind::Tar | SinkKind::Zip => Arc::new(ArchiveSink::new(config.sink)),
+        SinkKind::WebDav => Arc::new(WebDavSink::new(config, client)?),
+    })
+}
+
+/// Part file a download is written to before it is complete
+fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+/// Files stay in the collection folder, downloaded into a `.part` file and renamed once complete
+pub struct DirectorySink;
+
+struct DirectoryFile {
+    file: tokio::fs::File,
+    part: PathBuf,
+    path: PathBuf,
+}
+
+impl OutputSink for DirectorySink {
+    fn kind(&self) -> SinkKind {
+        SinkKind::Directory
+    }
+
+    fn contains<'a>(&'a self, output_dir: &'a Path, filename: &'a str) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move { Ok(tokio::fs::try_exists(output_dir.join(filename)).await?) })
+    }
+
+    fn create<'a>(&'a self, output_dir: &'a Path, filename: &'a str) -> BoxFuture<'a, Result<Box<dyn SinkFile>>> {
+        Box::pin(async move {
+            let path = output_dir.join(filename);
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+
+            let part = part_path(&path);
+            let file = tokio::fs::File::create(&part).await?;
+            Ok(Box::new(DirectoryFile { file, part, path }) as Box<dyn SinkFile>)
+        })
+    }
+}
+
+impl SinkFile for DirectoryFile {
+    fn write<'a>(&'a mut self, bytes: &'a [u8]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { Ok(self.file.write_all(bytes).await?) })
+    }
+
+    fn finish(mut self: Box<Self>) -> BoxFuture<'static, Result<()>> {
+        Box::pin(async move {
+            self.file.flush().await?;
+            self.file.shutdown().await?;
+            tokio::fs::rename(&self.part, &self.path).await?;
+            Ok(())
+        })
+    }
+
+    fn abort(mut self: Box<Self>) -> BoxFuture<'static, ()> {
+        Box::pin(async move {
+            let _ = self.file.shutdown().await;
+            let _ = tokio::fs::remove_file(&self.part).await;
+        })
+    }
+}
+
+/// Files are appended to `beatmaps.tar` or `beatmaps.zip` in the collection folder. Concurrent
+/// downloads can't write into one archive at once, so each is spooled next to the archive
+/// and appended when complete.
+pub struct ArchiveSink {
+    kind: SinkKind,
+    /// Appends are serialized, they all rewrite the end of the archive
+    lock: Arc<Mutex<()>>,
+    /// Entry names of each archive, read on first use
+    names: Arc<Mutex<HashMap<PathBuf, HashSet<String>>>>,
+}
+
+struct ArchiveFile {
+    kind: SinkKind,
+    file: tokio::fs::File,
+    spool: PathBuf,
+    archive: PathBuf,
+    name: String,
+    crc: u32,
+    size: u64,
+    lock: Arc<Mutex<()>>,
+    names: Arc<Mutex<HashMap<PathBuf, HashSet<String>>>>,
+}
+
+impl ArchiveSink {
+    fn new(kind: SinkKind) -> Self {
+        ArchiveSink {
+            kind,
+            lock: Arc::new(Mutex::new(())),
+            names: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl OutputSink for ArchiveSink {
+    fn kind(&self) -> SinkKind {
+        self.kind
+    }
+
+    fn contains<'a>(&'a self, output_dir: &'a Path, filename: &'a str) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            let archive = archive_path(output_dir, self.kind);
+            if let Some(names) = self.names.lock().unwrap_or_else(|e| e.into_inner()).get(&archive) {
+                return Ok(names.contains(filename));
+            }
+
+            let kind = self.kind;
+            let read = archive.clone();
+            let names = tokio::task::spawn_blocking(move || archive_entries(&read, kind))
+                .await
+                .map_err(|e| AppError::other_dynamic(e.to_string().into_boxed_str()))??;
+
+            let mut cache = self.names.lock().unwrap_or_else(|e| e.into_inner());
+            Ok(cache.entry(archive).or_insert(names).contains(filename))
+        })
+    }
+
+    fn create<'a>(&'a self, output_dir: &'a Path, filename: &'a str) -> BoxFuture<'a, Result<Box<dyn SinkFile>>> {
+        Box::pin(async move {
+            // Flat spool names, layout subfolders only exist inside the archive
+            let spool = output_dir.join(format!(".{}.part", filename.replace('/', "_")));
+            let file = tokio::fs::File::create(&spool).await?;
+
+            Ok(Box::new(ArchiveFile {
+                kind: self.kind,
+                file,
+                spool,
+                archive: archive_path(output_dir, self.kind),
+                name: filename.to_string(),
+                crc: 0,
+                size: 0,
+                lock: self.lock.clone(),
+                names: self.names.clone(),
+            }) as Box<dyn SinkFile>)
+        })
+    }
+}
+
+impl SinkFile for ArchiveFile {
+    fn write<'a>(&'a mut self, bytes: &'a [u8]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.file.write_all(bytes).await?;
+            if self.kind == SinkKind::Zip {
+                self.crc = zip::crc32(self.crc, bytes);
+            }
+            self.size += bytes.len() as u64;
+            Ok(())
+        })
+    }
+
+    fn finish(mut self: Box<Self>) -> BoxFuture<'static, Result<()>> {
+        Box::pin(async move {
+            self.file.flush().await?;
+            self.file.shutdown().await?;
+
+            let ArchiveFile { kind, spool, archive, name, crc, size, lock, names, .. } = *self;
+            let result = tokio::task::spawn_blocking(move || {
+                let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+                let appended = match kind {
+                    SinkKind::Zip => zip::append(&archive, &spool, &name, crc, size),
+                    _ => append_to_tar(&archive, &spool, &name),
+                };
+                let _ = std::fs::remove_file(&spool);
+                appended?;
+
+                if let Some(names) = names.lock().unwrap_or_else(|e| e.into_inner()).get_mut(&archive) {
+                    names.insert(name);
+                }
+                Ok(())
+            })
+            .await;
+
+            result.map_err(|e| AppError::other_dynamic(e.to_string().into_boxed_str()))?
+        })
+    }
+
+    fn abort(mut self: Box<Self>) -> BoxFuture<'static, ()> {
+        Box::pin(async move {
+            let _ = self.file.shutdown().await;
+            let _ = tokio::fs::remove_file(&self.spool).await;
+        })
+    }
+}
+
+/// Archive the tar or zip sink writes inside a collection folder
+fn archive_path(output_dir: &Path, kind: SinkKind) -> PathBuf {
+    output_dir.join(match kind {
+        SinkKind::Zip => "beatmaps.zip",
+        _ => "beatmaps.tar",
+    })
+}
+
+/// Names of the files in the archive of a collection folder, empty when there's none
+pub fn stored_entries(output_dir: &Path, kind: SinkKind) -> Result<HashSet<String>> {
+    archive_entries(&archive_path(output_dir, kind), kind)
+}
+
+fn archive_entries(archive: &Path, kind: SinkKind) -> Result<HashSet<String>> {
+    if kind == SinkKind::Zip {
+        return zip::entries(archive);
+    }
+
+    let file = match std::fs::File::open(archive) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut archive = tar::Archive::new(file);
+    let mut names = HashSet::new();
+    for entry in archive.entries()? {
+        names.insert(entry?.path()?.to_string_lossy().into_owned());
+    }
+
+    Ok(names)
+}
+
+/// Append a file to a tar archive, replacing the end-of-archive marker of the previous append
+fn append_to_tar(archive: &Path, path: &Path, name: &str) -> Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(archive)?;
+
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(TAR_TERMINATOR_SIZE)))?;
+
+    let mut builder = tar::Builder::new(file);
+    builder.append_path_with_name(path, name)?;
+    builder.finish()?;
+
+    Ok(())
+}
+
+/// Files are streamed to a WebDAV server as they download, into `<url>/<collection folder>/`
+pub struct WebDavSink {
+    client: reqwest::Client,
+    base: Url,
+    username: Option<Box<str>>,
+    password: Option<Box<str>>,
+    /// Remote folders known to exist
+    created: Mutex<HashSet<String>>,
+}
+
+struct WebDavFile {
+    sender: mpsc::Sender<std::io::Result<Vec<u8>>>,
+    upload: JoinHandle<reqwest::Result<reqwest::Response>>,
+    remote_path: String,
+}
+
+impl WebDavSink {
+    fn new(config: &OutputConfig, client: &reqwest::Client) -> Result<Self> {
+        let url = config.url.as_deref()
+            .ok_or(AppError::other("output.url is required for the webdav sink"))?;
+        // A trailing slash makes joined paths land inside the base folder
+        let base = Url::parse(&format!("{}/", url.trim_end_matches('/')))
+            .map_err(|e| AppError::other_dynamic(format!("Invalid output.url: {}", e).into_boxed_str()))?;
+
+        Ok(WebDavSink {
+            client: client.clone(),
+            base,
+            username: config.username.clone(),
+            password: config.password.clone(),
+            created: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// `<collection folder>/<filename>`
+    fn remote_path(output_dir: &Path, filename: &str) -> String {
+        let folder = output_dir.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        format!("{}/{}", folder, filename)
+    }
+
+    /// URL of a remote file, creating its folders first when `create_folders` is set
+    async fn file_url(&self, remote_path: &str, create_folders: bool) -> Result<Url> {
+        let segments: Vec<&str> = remote_path.split('/').filter(|s| !s.is_empty()).collect();
+        let Some((file, folders)) = segments.split_last() else {
+            return Err(AppError::other("Empty WebDAV path"));
+        };
+
+        let mut url = self.base.clone();
+        for (depth, folder) in folders.iter().enumerate() {
+            url.path_segments_mut()
+                .map_err(|()| AppError::other("output.url can't be a base URL"))?
+                .pop_if_empty()
+                .push(folder)
+                .push("");
+
+            let key = folders[..=depth].join("/");
+            if !create_folders || self.created.lock().unwrap_or_else(|e| e.into_inner()).contains(&key) {
+                continue;
+            }
+
+            let method = reqwest::Method::from_bytes(b"MKCOL").expect("valid method");
+            let status = self.authorize(self.client.request(method, url.clone())).send().await?.status();
+            // 405: the folder already exists
+            if !status.is_success() && status != reqwest::StatusCode::METHOD_NOT_ALLOWED {
+                return Err(AppError::api_dynamic(
+                    format!("WebDAV MKCOL {} failed: HTTP {}", key, status).into_boxed_str()
+                ));
+            }
+            self.created.lock().unwrap_or_else(|e| e.into_inner()).insert(key);
+        }
+
+        url.path_segments_mut()
+            .map_err(|()| AppError::other("output.url can't be a base URL"))?
+            .pop_if_empty()
+            .push(file);
+
+        Ok(url)
+    }
+
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.username {
+            Some(username) => request.basic_auth(username, self.password.as_deref()),
+            None => request,
+        }
+    }
+}
+
+impl OutputSink for WebDavSink {
+    fn kind(&self) -> SinkKind {
+        SinkKind::WebDav
+    }
+
+    fn contains<'a>(&'a self, output_dir: &'a Path, filename: &'a str) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            let url = self.file_url(&Self::remote_path(output_dir, filename), false).await?;
+            let status = self.authorize(self.client.head(url)).send().await?.status();
+
+            match status {
+                status if status.is_success() => Ok(true),
+                reqwest::StatusCode::NOT_FOUND => Ok(false),
+                status => Err(AppError::api_dynamic(
+                    format!("WebDAV HEAD {} failed: HTTP {}", filename, status).into_boxed_str()
+                )),
+            }
+        })
+    }
+
+    fn create<'a>(&'a self, output_dir: &'a Path, filename: &'a str) -> BoxFuture<'a, Result<Box<dyn SinkFile>>> {
+        Box::pin(async move {
+            let remote_path = Self::remote_path(output_dir, filename);
+            let url = self.file_url(&remote_path, true).await?;
+
+            // The request body is fed by the download, so nothing waits on disk or in memory
+            let (sender, receiver) = mpsc::channel(UPLOAD_QUEUE_CHUNKS);
+            let body = futures_util::stream::unfold(receiver, |mut receiver| async move {
+                receiver.recv().await.map(|chunk| (chunk, receiver))
+            });
+            let request = self.authorize(self.client.put(url)).body(reqwest::Body::wrap_stream(body));
+            let upload = tokio::spawn(request.send());
+
+            Ok(Box::new(WebDavFile { sender, upload, remote_path }) as Box<dyn SinkFile>)
+        })
+    }
+}
+
+impl WebDavFile {
+    async fn response(upload: JoinHandle<reqwest::Result<reqwest::Response>>, remote_path: &str) -> Result<()> {
+        let response = upload.await
+            .map_err(|e| AppError::other_dynamic(e.to_string().into_boxed_str()))??;
+
+        if !response.status().is_success() {
+            return Err(AppError::api_dynamic(
+                format!("WebDAV upload of {} failed: HTTP {}", remote_path, response.status()).into_boxed_str()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl SinkFile for WebDavFile {
+    fn write<'a>(&'a mut self, bytes: &'a [u8]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            if self.sender.send(Ok(bytes.to_vec())).await.is_err() {
+                // The request ended early, its response tells why
+                let upload = std::mem::replace(&mut self.upload, tokio::spawn(std::future::pending()));
+                Self::response(upload, &self.remote_path).await?;
+                return Err(AppError::api_dynamic(
+                    format!("WebDAV upload of {} ended early", self.remote_path).into_boxed_str()
+                ));
+            }
+            Ok(())
+        })
+    }
+
+    fn finish(self: Box<Self>) -> BoxFuture<'static, Result<()>> {
+        Box::pin(async move {
+            let WebDavFile { sender, upload, remote_path } = *self;
+            drop(sender);
+            Self::response(upload, &remote_path).await
+        })
+    }
+
+    fn abort(self: Box<Self>) -> BoxFuture<'static, ()> {
+        Box::pin(async move {
+            // A failing body makes the client drop the request, the server keeps nothing
+            let _ = self.sender.send(Err(std::io::Error::other("download aborted"))).await;
+            drop(self.sender);
+            let _ = self.upload.await;
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn archive_sinks_store_streamed_files() {
+        let dir = std::env::temp_dir().join(format!("osu-collect-sink-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for kind in [SinkKind::Tar, SinkKind::Zip] {
+            let sink = ArchiveSink::new(kind);
+            assert!(!sink.contains(&dir, "Artist/1 a.osz").await.unwrap());
+
+            let mut file = sink.create(&dir, "Artist/1 a.osz").await.unwrap();
+            file.write(b"PK\x03\x04 first").await.unwrap();
+            file.write(b" chunk").await.unwrap();
+            file.finish().await.unwrap();
+
+            // An aborted file leaves nothing behind
+            let mut file = sink.create(&dir, "2 b.osz").await.unwrap();
+            file.write(b"PK\x03\x04").await.unwrap();
+            file.abort().await;
+
+            assert!(sink.contains(&dir, "Artist/1 a.osz").await.unwrap());
+            assert!(!sink.contains(&dir, "2 b.osz").await.unwrap());
+            assert_eq!(stored_entries(&dir, kind).unwrap(), HashSet::from(["Artist/1 a.osz".to_string()]));
+        }
+
+        // Only the two archives remain, spool files are gone
+        let mut names: Vec<String> = std::fs::read_dir(&dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["beatmaps.tar", "beatmaps.zip"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,162 @@
+use crate::error::{AppError, Result};
+use crate::utils::{checksum, write_atomic};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+pub const STATE_FILE_NAME: &str = ".osu-collect.json";
+const STATE_VERSION: u32 = 1;
+
+/// Per-collection state kept in the collection folder between runs
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct CollectionState {
+    /// Beatmapsets that were not found on any mirror
+    #[serde(default)]
+    pub unavailable: BTreeSet<u32>,
+    /// Existing files skipped by `download.file_exists_default` because no prompt could be
+    /// shown, by beatmapset ID, until a later run downloads or explicitly skips them
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub needs_attention: BTreeMap<u32, Box<str>>,
+    /// Set while a run is in progress and cleared once collection.db and the manifest
+    /// were both written, so a crash in between is noticed later
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub incomplete: bool,
+}
+
+/// On-disk layout of the state file
+#[derive(Debug, Deserialize, Serialize)]
+struct StateFile {
+    version: u32,
+    checksum: Box<str>,
+    state: serde_json::Value,
+}
+
+impl CollectionState {
+    /// Load state from the collection folder, missing state starts fresh.
+    /// Unreadable state is moved aside to a `.bak` file instead of being overwritten.
+    pub fn load(output_dir: &Path) -> Self {
+        let path = output_dir.join(STATE_FILE_NAME);
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            return Self::default();
+        };
+
+        match Self::parse(&contents) {
+            Ok(state) => state,
+            Err(e) => {
+                let backup = path.with_extension("json.bak");
+                let _ = std::fs::rename(&path, &backup);
+                eprintln!("\x1b[33m⚠\x1b[0m Warning: {} (moved to {})", e, backup.display());
+                Self::default()
+            }
+        }
+    }
+
+    fn parse(contents: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(contents)?;
+
+        let (version, mut state) = match serde_json::from_value::<StateFile>(value.clone()) {
+            Ok(file) => {
+                if checksum(file.state.to_string().as_bytes()) != *file.checksum {
+                    return Err(AppError::other("State file checksum mismatch"));
+                }
+                (file.version, file.state)
+            }
+            // Version 0 stored the state directly, without the envelope
+            Err(_) => (0, value),
+        };
+
+        if version > STATE_VERSION {
+            return Err(AppError::other_dynamic(
+                format!("State file version {} is newer than supported ({})", version, STATE_VERSION)
+                    .into_boxed_str()
+            ));
+        }
+
+        migrate(&mut state, version);
+        Ok(serde_json::from_value(state)?)
+    }
+
+    /// Save state into the collection folder
+    pub fn save(&self, output_dir: &Path) -> Result<()> {
+        let state = serde_json::to_value(self)?;
+        let file = StateFile {
+            version: STATE_VERSION,
+            checksum: checksum(state.to_string().as_bytes()).into_boxed_str(),
+            state,
+        };
+
+        let contents = serde_json::to_string_pretty(&file)?;
+        write_atomic(&output_dir.join(STATE_FILE_NAME), contents).map_err(|e| {
+            AppError::other_dynamic(
+                format!("Failed to write {}: {}", STATE_FILE_NAME, e).into_boxed_str()
+            )
+        })
+    }
+}
+
+/// Bring state written by an older release up to the current version, one step at a time
+fn migrate(_state: &mut serde_json::Value, from: u32) {
+    for version in from..STATE_VERSION {
+        match version {
+            // 0 -> 1: state moved into the versioned, checksummed envelope, contents unchanged
+            0 => {}
+            _ => unreachable!("no migration from state version {}", version),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn envelope(version: u32, state: serde_json::Value) -> String {
+        serde_json::json!({
+            "version": version,
+            "checksum": checksum(state.to_string().as_bytes()),
+            "state": state,
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn parses_current_version() {
+        let state = CollectionState::parse(&envelope(STATE_VERSION, serde_json::json!({ "unavailable": [3, 1] }))).unwrap();
+
+        assert_eq!(state.unavailable.into_iter().collect::<Vec<_>>(), [1, 3]);
+        assert!(!state.incomplete);
+    }
+
+    #[test]
+    fn migrates_unversioned_state() {
+        let state = CollectionState::parse(r#"{ "unavailable": [5], "incomplete": true }"#).unwrap();
+
+        assert!(state.unavailable.contains(&5));
+        assert!(state.incomplete);
+    }
+
+    #[test]
+    fn keeps_needs_attention_files() {
+        let mut state = CollectionState::default();
+        state.needs_attention.insert(7, "7 Artist - Title.osz".into());
+
+        let saved = serde_json::to_value(&state).unwrap();
+        assert_eq!(saved["needs_attention"]["7"], "7 Artist - Title.osz");
+
+        let state = CollectionState::parse(&envelope(STATE_VERSION, saved)).unwrap();
+        assert_eq!(state.needs_attention.get(&7).map(|name| &**name), Some("7 Artist - Title.osz"));
+        assert!(!serde_json::to_value(CollectionState::default()).unwrap().as_object().unwrap().contains_key("needs_attention"));
+    }
+
+    #[test]
+    fn rejects_checksum_mismatch() {
+        let contents = envelope(STATE_VERSION, serde_json::json!({ "unavailable": [1] }))
+            .replace("[1]", "[2]");
+
+        assert!(CollectionState::parse(&contents).is_err());
+    }
+
+    #[test]
+    fn rejects_newer_version() {
+        assert!(CollectionState::parse(&envelope(STATE_VERSION + 1, serde_json::json!({}))).is_err());
+    }
+}
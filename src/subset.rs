@@ -0,0 +1,158 @@
+use crate::collection::load_collection_metadata;
+use crate::collector::{Beatmap, Beatmapset};
+use crate::error::{AppError, Result};
+use crate::manifest::Manifest;
+use serde::Serialize;
+use std::path::Path;
+
+/// Condition of `export-subset --filter`, e.g. `status=ranked`, `sr>=5` or `diff~insane`
+#[derive(Debug, Clone)]
+pub enum Filter {
+    Status(Box<str>),
+    MinStars(f64),
+    MaxStars(f64),
+    Artist(Box<str>),
+    Title(Box<str>),
+    Diff(Box<str>),
+}
+
+/// Parse a filter for clap
+pub fn parse_filter(input: &str) -> std::result::Result<Filter, String> {
+    let invalid = || format!(
+        "invalid filter '{}', expected status=NAME, sr>=N, sr<=N, artist~TEXT, title~TEXT or diff~TEXT",
+        input
+    );
+
+    // The earliest operator splits, so values may contain operator characters (`title~a=b`).
+    // Two-character operators win over one-character ones starting at the same position.
+    let (index, op) = [">=", "<=", "=", "~"].into_iter()
+        .filter_map(|op| input.find(op).map(|index| (index, op)))
+        .min_by_key(|(index, op)| (*index, std::cmp::Reverse(op.len())))
+        .ok_or_else(invalid)?;
+    let (key, value) = (input[..index].trim(), input[index + op.len()..].trim());
+    let text = || value.to_lowercase().into_boxed_str();
+    let stars = || value.parse::<f64>().map_err(|_| invalid());
+
+    Ok(match (key, op) {
+        ("status", "=") => Filter::Status(text()),
+        ("sr", ">=") => Filter::MinStars(stars()?),
+        ("sr", "<=") => Filter::MaxStars(stars()?),
+        ("artist", "~") => Filter::Artist(text()),
+        ("title", "~") => Filter::Title(text()),
+        ("diff", "~") => Filter::Diff(text()),
+        _ => return Err(invalid()),
+    })
+}
+
+impl Filter {
+    fn matches_set(&self, beatmapset: &Beatmapset) -> bool {
+        let contains = |field: &Option<Box<str>>, text: &str| {
+            field.as_deref().is_some_and(|field| field.to_lowercase().contains(text))
+        };
+
+        match self {
+            Filter::Status(status) => beatmapset.status_name() == Some(status),
+            Filter::Artist(text) => contains(&beatmapset.artist, text),
+            Filter::Title(text) => contains(&beatmapset.title, text),
+            _ => true,
+        }
+    }
+
+    fn matches_beatmap(&self, beatmap: &Beatmap) -> bool {
+        match self {
+            Filter::MinStars(min) => beatmap.difficulty_rating.is_some_and(|sr| sr >= *min),
+            Filter::MaxStars(max) => beatmap.difficulty_rating.is_some_and(|sr| sr <= *max),
+            Filter::Diff(text) => beatmap.version.as_deref()
+                .is_some_and(|name| name.to_lowercase().contains(&**text)),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SubsetEntry<'a> {
+    beatmapset_id: u32,
+    beatmap_id: u32,
+    md5: &'a str,
+}
+
+/// Write the downloaded sets of a collection folder matching every filter as a shareable
+/// list of IDs and checksums, CSV or JSON depending on the extension of `to`.
+/// Returns how many sets and beatmaps were exported.
+pub fn export_subset(dir: &Path, filters: &[Filter], to: &Path) -> Result<(usize, usize)> {
+    let collection = load_collection_metadata(dir)?;
+    let manifest = Manifest::load(dir)?;
+
+    let mut sets = 0;
+    let mut entries = Vec::new();
+
+    for beatmapset in &collection.beatmapsets {
+        if !manifest.files.contains_key(&beatmapset.id)
+            || !filters.iter().all(|filter| filter.matches_set(beatmapset))
+        {
+            continue;
+        }
+
+        let before = entries.len();
+        entries.extend(beatmapset.beatmaps.iter()
+            .filter(|beatmap| filters.iter().all(|filter| filter.matches_beatmap(beatmap)))
+            .map(|beatmap| SubsetEntry {
+                beatmapset_id: beatmapset.id,
+                beatmap_id: beatmap.id,
+                md5: &beatmap.checksum,
+            }));
+        sets += usize::from(entries.len() > before);
+    }
+
+    let is_json = to.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    let contents = if is_json {
+        serde_json::to_string_pretty(&entries)?
+    } else {
+        let mut csv = String::from("beatmapset_id,beatmap_id,md5\n");
+        for entry in &entries {
+            csv.push_str(&format!("{},{},{}\n", entry.beatmapset_id, entry.beatmap_id, entry.md5));
+        }
+        csv
+    };
+
+    std::fs::write(to, contents).map_err(|e| {
+        AppError::other_dynamic(
+            format!("Failed to write {}: {}", to.display(), e).into_boxed_str()
+        )
+    })?;
+
+    Ok((sets, entries.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_filter_accepts_every_condition() {
+        assert!(matches!(parse_filter("status=Ranked"), Ok(Filter::Status(s)) if &*s == "ranked"));
+        assert!(matches!(parse_filter("sr >= 5.5"), Ok(Filter::MinStars(sr)) if sr == 5.5));
+        assert!(matches!(parse_filter("sr<=3"), Ok(Filter::MaxStars(sr)) if sr == 3.0));
+        assert!(matches!(parse_filter("artist~Camellia"), Ok(Filter::Artist(s)) if &*s == "camellia"));
+        assert!(matches!(parse_filter("title~ Blue Zenith "), Ok(Filter::Title(s)) if &*s == "blue zenith"));
+        assert!(matches!(parse_filter("diff~Insane"), Ok(Filter::Diff(s)) if &*s == "insane"));
+    }
+
+    #[test]
+    fn parse_filter_keeps_operator_characters_in_values() {
+        assert!(matches!(parse_filter("title~a=b"), Ok(Filter::Title(s)) if &*s == "a=b"));
+        assert!(matches!(parse_filter("artist~x>=y"), Ok(Filter::Artist(s)) if &*s == "x>=y"));
+        assert!(matches!(parse_filter("diff~<=3 ~hard~"), Ok(Filter::Diff(s)) if &*s == "<=3 ~hard~"));
+        assert!(matches!(parse_filter("status=a~b"), Ok(Filter::Status(s)) if &*s == "a~b"));
+        assert!(parse_filter("sr>==5").is_err());
+    }
+
+    #[test]
+    fn parse_filter_rejects_invalid_conditions() {
+        assert!(parse_filter("sr>=fast").is_err());
+        assert!(parse_filter("sr=5").is_err());
+        assert!(parse_filter("status~ranked").is_err());
+        assert!(parse_filter("mapper~someone").is_err());
+        assert!(parse_filter("ranked").is_err());
+    }
+}
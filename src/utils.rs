@@ -1,6 +1,20 @@
 use crate::error::{AppError, Result};
+use std::collections::VecDeque;
+use std::io::IsTerminal;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use unicode_normalization::UnicodeNormalization;
 use url::Url;
 
+/// NFC-normalize a name and drop zero-width and control characters, so the same
+/// collection name maps to the same folder on every OS
+pub fn normalize_name(name: &str) -> String {
+    name.nfc()
+        .filter(|c| !c.is_control() && !matches!(c, '\u{200B}'..='\u{200F}' | '\u{2060}' | '\u{FEFF}'))
+        .collect()
+}
+
 pub fn sanitize_filename(filename: &str) -> String {
     filename
         .chars()
@@ -13,11 +27,31 @@ pub fn sanitize_filename(filename: &str) -> String {
         .to_string()
 }
 
-pub fn parse_collection_id(input: &str) -> Result<u32> {
+/// What a collection URL or ID points to on osu!collector
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Collection(u32),
+    Tournament(u32),
+    /// `<name>:<id>` of a source defined in the config
+    Custom { name: Box<str>, id: u32 },
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Collection(id) => write!(f, "collection:{}", id),
+            Source::Tournament(id) => write!(f, "tournament:{}", id),
+            Source::Custom { name, id } => write!(f, "{}:{}", name, id),
+        }
+    }
+}
+
+pub fn parse_source(input: &str) -> Result<Source> {
     let trimmed = input.trim();
 
     if trimmed.bytes().all(|b| b.is_ascii_digit()) {
         return trimmed.parse::<u32>()
+            .map(Source::Collection)
             .map_err(|_| AppError::invalid_url_dynamic(
                 format!("Invalid collection ID: {}", trimmed).into_boxed_str()
             ));
@@ -29,6 +63,15 @@ pub fn parse_collection_id(input: &str) -> Result<u32> {
         ));
     }
 
+    if let Some((name, id)) = trimmed.split_once(':')
+        && !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        && !matches!(name, "http" | "https")
+        && let Ok(id) = id.parse::<u32>()
+    {
+        return Ok(Source::Custom { name: name.into(), id });
+    }
+
     let url = Url::parse(trimmed)
         .map_err(|_| AppError::invalid_url_dynamic(
             format!("Invalid URL or collection ID: {}", trimmed).into_boxed_str()
@@ -50,16 +93,351 @@ pub fn parse_collection_id(input: &str) -> Result<u32> {
         .ok_or(AppError::invalid_url("Invalid URL path"))?
         .collect();
 
-    if path_segments.len() < 2 || path_segments[0] != "collections" {
+    if path_segments.len() < 2 || !matches!(path_segments[0], "collections" | "tournaments") {
         return Err(AppError::invalid_url(
-            "URL must be in format: https://osucollector.com/collections/{id}"
+            "URL must be in format: https://osucollector.com/collections/{id} or https://osucollector.com/tournaments/{id}"
         ));
     }
 
     let id = path_segments[1];
 
-    id.parse::<u32>()
+    let id = id.parse::<u32>()
         .map_err(|_| AppError::invalid_url_dynamic(
             format!("Collection ID must be numeric, got: {}", id).into_boxed_str()
-        ))
+        ))?;
+
+    Ok(match path_segments[0] {
+        "tournaments" => Source::Tournament(id),
+        _ => Source::Collection(id),
+    })
+}
+
+/// Look up a value in a JSON document by a dotted path (e.g. `data.token` or `items.0.id`)
+pub fn json_path<'a>(value: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    path.split('.')
+        .filter(|segment| !segment.is_empty())
+        .try_fold(value, |current, segment| match current {
+            serde_json::Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => current.get(segment),
+        })
+}
+
+/// Replace `{name}` placeholders in a template with the given variables
+pub fn fill_template(template: &str, vars: &[(Box<str>, Box<str>)]) -> String {
+    vars.iter().fold(template.to_string(), |acc, (name, value)| {
+        acc.replace(&format!("{{{}}}", name), value)
+    })
+}
+
+/// Convert an internationalized host in a URL template to its ASCII (punycode) form.
+/// Only the scheme and authority are parsed, a full parse would percent-encode the
+/// `{placeholders}` in the path. `None` when the host is invalid.
+pub fn normalize_url_host(template: &str) -> Option<String> {
+    let (scheme, rest) = template.split_once("://")?;
+    let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (authority, path) = rest.split_at(authority_end);
+
+    // Placeholders in the host can only be checked once they are filled in
+    if authority.is_ascii() || authority.contains('{') {
+        return Some(template.to_string());
+    }
+
+    let url = Url::parse(&format!("{}://{}", scheme, authority)).ok()?;
+    url.host_str()?;
+    let origin = url.as_str().strip_suffix('/').unwrap_or(url.as_str());
+
+    Some(format!("{}{}", origin, path))
+}
+
+/// Shuffle in place (Fisher-Yates) with a per-process random seed, good enough for scheduling
+pub fn shuffle<T>(items: &mut [T]) {
+    use std::hash::{BuildHasher, Hasher};
+
+    let mut state = std::collections::hash_map::RandomState::new().build_hasher().finish() | 1;
+    for i in (1..items.len()).rev() {
+        // xorshift64
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        items.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+}
+
+/// Path next to `path` that files are written to before being renamed into place
+pub fn temp_path(path: &std::path::Path) -> std::path::PathBuf {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    temp.into()
+}
+
+/// Write a file through a temporary file and a rename, so readers never see it half-written
+pub fn write_atomic(path: &std::path::Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let temp = temp_path(path);
+    let result = std::fs::File::create(&temp).and_then(|mut file| {
+        std::io::Write::write_all(&mut file, contents.as_ref())?;
+        file.sync_all()
+    });
+
+    match result.and_then(|()| std::fs::rename(&temp, path)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = std::fs::remove_file(&temp);
+            Err(e)
+        }
+    }
+}
+
+/// FNV-1a checksum used to detect corrupted state files
+pub fn checksum(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf29ce484222325_u64, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// Unix time in seconds as UTC `YYYY-MM-DDTHH:MM:SSZ`
+pub fn format_timestamp(secs: i64) -> String {
+    let time = secs.rem_euclid(86_400);
+
+    format!(
+        "{}T{:02}:{:02}:{:02}Z",
+        crate::collector::date_from_unix(secs),
+        time / 3600,
+        time % 3600 / 60,
+        time % 60,
+    )
+}
+
+/// Ask a yes/no question on stderr, anything but `y`/`yes` counts as no
+pub fn confirm(prompt: &str) -> Result<bool> {
+    eprint!("{}", prompt);
+
+    if !std::io::stdin().is_terminal() {
+        eprintln!("n (stdin is not a terminal)");
+        return Ok(false);
+    }
+
+    std::io::Write::flush(&mut std::io::stderr())?;
+
+    let input = read_line()?;
+
+    Ok(matches!(input.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Whether `route_stdin_lines` is reading stdin
+static STDIN_ROUTED: AtomicBool = AtomicBool::new(false);
+/// Prompts waiting for a line while stdin is routed, answered in the order they asked
+static LINE_WAITERS: Mutex<VecDeque<Sender<String>>> = Mutex::new(VecDeque::new());
+
+/// Read stdin on a background thread for the rest of the process. Each line answers the
+/// oldest waiting `read_line`, lines typed while no prompt waits go to `other` (e.g. key
+/// commands during a run) instead, so neither steals the other's input.
+pub fn route_stdin_lines(other: impl Fn(String) + Send + 'static) {
+    if STDIN_ROUTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines() {
+            let Ok(line) = line else { break };
+            let waiter = LINE_WAITERS.lock().unwrap().pop_front();
+
+            match waiter {
+                Some(waiter) => {
+                    let _ = waiter.send(line);
+                }
+                None => other(line),
+            }
+        }
+
+        // End of input, waiting prompts get an empty answer and later ones read stdin themselves
+        let mut waiters = LINE_WAITERS.lock().unwrap();
+        STDIN_ROUTED.store(false, Ordering::SeqCst);
+        waiters.clear();
+    });
+}
+
+/// Read a line from stdin, from `route_stdin_lines` once it reads stdin. Empty at end of input.
+pub fn read_line() -> std::io::Result<String> {
+    let receiver = {
+        let mut waiters = LINE_WAITERS.lock().unwrap();
+        STDIN_ROUTED.load(Ordering::SeqCst).then(|| {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            waiters.push_back(sender);
+            receiver
+        })
+    };
+
+    match receiver {
+        Some(receiver) => Ok(receiver.recv().unwrap_or_default()),
+        None => {
+            let mut input = String::new();
+            std::io::stdin().read_line(&mut input)?;
+            Ok(input)
+        }
+    }
+}
+
+/// Page of a beatmapset on the osu! website
+#[inline]
+pub fn beatmapset_url(beatmapset_id: u32) -> String {
+    format!("https://osu.ppy.sh/beatmapsets/{}", beatmapset_id)
+}
+
+/// Open a URL in the default browser
+pub fn open_in_browser(url: &str) -> std::io::Result<()> {
+    #[cfg(windows)]
+    let mut command = {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    };
+    #[cfg(target_os = "macos")]
+    let mut command = std::process::Command::new("open");
+    #[cfg(all(unix, not(target_os = "macos")))]
+    let mut command = std::process::Command::new("xdg-open");
+
+    command.arg(url)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .map(drop)
+}
+
+/// Whether stdout is a terminal known to support OSC 8 hyperlinks
+pub fn supports_hyperlinks() -> bool {
+    if !std::io::stdout().is_terminal() {
+        return false;
+    }
+
+    let env = |name: &str| std::env::var(name).unwrap_or_default();
+
+    if ["WT_SESSION", "VTE_VERSION", "KONSOLE_VERSION", "KITTY_WINDOW_ID", "WEZTERM_EXECUTABLE"]
+        .iter()
+        .any(|name| std::env::var_os(name).is_some())
+    {
+        return true;
+    }
+
+    let term_program = env("TERM_PROGRAM");
+    if matches!(term_program.as_str(), "iTerm.app" | "WezTerm" | "vscode" | "ghostty" | "Hyper") {
+        return true;
+    }
+
+    let term = env("TERM");
+    ["kitty", "alacritty", "foot", "ghostty"].iter().any(|name| term.contains(name))
+}
+
+/// Wrap text in an OSC 8 terminal hyperlink
+#[inline]
+pub fn hyperlink(url: &str, text: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+}
+
+/// Quote a command line argument for the current platform's shell when needed
+pub fn shell_quote(arg: &str) -> std::borrow::Cow<'_, str> {
+    let plain = !arg.is_empty()
+        && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:=@,+%~".contains(c));
+    if plain {
+        return arg.into();
+    }
+
+    if cfg!(windows) {
+        format!("\"{}\"", arg.replace('"', "\\\"")).into()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''")).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(Box<str>, Box<str>)> {
+        pairs.iter().map(|(name, value)| ((*name).into(), (*value).into())).collect()
+    }
+
+    #[test]
+    fn fill_template_replaces_every_placeholder() {
+        let vars = vars(&[("id", "123"), ("token", "abc")]);
+        assert_eq!(
+            fill_template("https://example.com/d/{id}?t={token}&again={id}", &vars),
+            "https://example.com/d/123?t=abc&again=123"
+        );
+    }
+
+    #[test]
+    fn fill_template_keeps_unknown_placeholders() {
+        assert_eq!(fill_template("/d/{id}/{missing}", &vars(&[("id", "1")])), "/d/1/{missing}");
+    }
+
+    #[test]
+    fn json_path_walks_objects_and_arrays() {
+        let json = serde_json::json!({ "data": { "token": "abc", "items": [{ "id": 7 }] } });
+
+        assert_eq!(json_path(&json, "data.token"), Some(&serde_json::json!("abc")));
+        assert_eq!(json_path(&json, "data.items.0.id"), Some(&serde_json::json!(7)));
+        assert_eq!(json_path(&json, ""), Some(&json));
+    }
+
+    #[test]
+    fn json_path_misses() {
+        let json = serde_json::json!({ "items": [1, 2] });
+
+        assert_eq!(json_path(&json, "items.5"), None);
+        assert_eq!(json_path(&json, "items.first"), None);
+        assert_eq!(json_path(&json, "other"), None);
+    }
+
+    #[test]
+    fn normalize_url_host_keeps_ascii_and_placeholder_hosts() {
+        let ascii = "https://api.nerinyan.moe/d/{id}";
+        let placeholder = "https://{host}/d/{id}";
+
+        assert_eq!(normalize_url_host(ascii).as_deref(), Some(ascii));
+        assert_eq!(normalize_url_host(placeholder).as_deref(), Some(placeholder));
+    }
+
+    #[test]
+    fn normalize_url_host_converts_idn_to_punycode() {
+        assert_eq!(
+            normalize_url_host("https://bücher.example/d/{id}?key=ü").as_deref(),
+            Some("https://xn--bcher-kva.example/d/{id}?key=ü")
+        );
+    }
+
+    #[test]
+    fn normalize_url_host_rejects_invalid_urls() {
+        assert_eq!(normalize_url_host("bücher.example/d/{id}"), None);
+        assert_eq!(normalize_url_host("https://bü cher.example/d/{id}"), None);
+    }
+
+    #[test]
+    fn shell_quote_leaves_plain_arguments() {
+        assert_eq!(shell_quote("--output=./songs"), "--output=./songs");
+        assert_eq!(shell_quote("https://osucollector.com/collections/44"), "https://osucollector.com/collections/44");
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn shell_quote_escapes_for_posix_shells() {
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("my songs"), "'my songs'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn shell_quote_escapes_for_windows() {
+        assert_eq!(shell_quote(""), "\"\"");
+        assert_eq!(shell_quote("my songs"), "\"my songs\"");
+        assert_eq!(shell_quote("say \"hi\""), "\"say \\\"hi\\\"\"");
+    }
+
+    #[test]
+    fn format_timestamp_is_utc_iso() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(1_709_251_199), "2024-02-29T23:59:59Z");
+        assert_eq!(format_timestamp(-1), "1969-12-31T23:59:59Z");
+    }
 }
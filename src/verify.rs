@@ -0,0 +1,309 @@
+use crate::collection;
+use crate::collector::Beatmapset;
+use crate::config::Config;
+use crate::downloader::{self, DownloadContext, DownloadResult};
+use crate::error::{AppError, Result};
+use crate::manifest::{Manifest, VerifyStatus, MANIFEST_FILE_NAME};
+use crate::mirror::RateLimits;
+use crate::options::SinkKind;
+use crate::profile::Profile;
+use crate::sink;
+use crate::state::CollectionState;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use std::sync::Arc;
+
+const QUARANTINE_DIR: &str = "quarantine";
+
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub ok: u32,
+    pub unchanged: u32,
+    /// Files uploaded to a remote sink, which can't be checked from here
+    pub remote: u32,
+    pub missing: Vec<(u32, Box<str>)>,
+    /// Corrupt files, moved to `quarantine/` unless verifying read-only
+    pub corrupt: Vec<(u32, Box<str>)>,
+    /// Corrupt sets a fresh copy was downloaded for
+    pub repaired: Vec<u32>,
+    /// The last download run stopped before collection.db and the manifest were both written
+    pub incomplete: bool,
+}
+
+/// Overall result, exit codes follow the Nagios plugin convention
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Health {
+    Ok,
+    /// Missing files or an unfinished run
+    Warning,
+    /// Corrupt files that weren't replaced
+    Critical,
+}
+
+impl Health {
+    #[inline]
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Health::Ok => 0,
+            Health::Warning => 1,
+            Health::Critical => 2,
+        }
+    }
+
+    #[inline]
+    fn label(self) -> &'static str {
+        match self {
+            Health::Ok => "OK",
+            Health::Warning => "WARNING",
+            Health::Critical => "CRITICAL",
+        }
+    }
+}
+
+/// Exit code for when verification couldn't run at all
+pub const EXIT_UNKNOWN: i32 = 3;
+
+impl VerifyReport {
+    pub fn health(&self) -> Health {
+        if self.corrupt.len() > self.repaired.len() {
+            Health::Critical
+        } else if !self.missing.is_empty() || self.incomplete {
+            Health::Warning
+        } else {
+            Health::Ok
+        }
+    }
+}
+
+/// Verify every file recorded in the manifest of a collection folder.
+/// Corrupt files are moved to `quarantine/` and dropped from the manifest, unless `read_only`
+/// is set, which leaves the folder and the manifest untouched.
+pub fn verify_collection(output_dir: &Path, deep: bool, read_only: bool) -> Result<VerifyReport> {
+    if !output_dir.join(MANIFEST_FILE_NAME).exists() {
+        return Err(AppError::other_dynamic(
+            format!("No {} found in '{}'", MANIFEST_FILE_NAME, output_dir.display()).into_boxed_str()
+        ));
+    }
+
+    let mut manifest = Manifest::load(output_dir)?;
+    let mut report = VerifyReport {
+        incomplete: CollectionState::load(output_dir).incomplete,
+        ..VerifyReport::default()
+    };
+
+    // Files in beatmaps.tar or beatmaps.zip are only checked for being in the archive
+    let tar_entries = sink::stored_entries(output_dir, SinkKind::Tar)?;
+    let zip_entries = sink::stored_entries(output_dir, SinkKind::Zip)?;
+
+    let ids: Vec<u32> = manifest.files.keys().copied().collect();
+    for beatmapset_id in ids {
+        let entry = &manifest.files[&beatmapset_id];
+        let filename = entry.filename.clone();
+
+        match entry.sink {
+            SinkKind::Directory => {}
+            SinkKind::Tar if tar_entries.contains(&*filename) => {
+                report.ok += 1;
+                continue;
+            }
+            SinkKind::Zip if zip_entries.contains(&*filename) => {
+                report.ok += 1;
+                continue;
+            }
+            SinkKind::Tar | SinkKind::Zip => {
+                report.missing.push((beatmapset_id, filename));
+                continue;
+            }
+            SinkKind::WebDav => {
+                report.remote += 1;
+                continue;
+            }
+        }
+
+        match manifest.verify_entry(output_dir, beatmapset_id, deep)? {
+            VerifyStatus::Ok => report.ok += 1,
+            VerifyStatus::Unchanged => report.unchanged += 1,
+            VerifyStatus::Missing => report.missing.push((beatmapset_id, filename)),
+            VerifyStatus::Corrupt(_) if read_only => report.corrupt.push((beatmapset_id, filename)),
+            VerifyStatus::Corrupt(reason) => {
+                let moved_to = quarantine(output_dir, beatmapset_id, &filename, &reason)?;
+                println!("\x1b[33m⚠\x1b[0m Quarantined {}: {} (moved to {})", filename, reason, moved_to.display());
+                manifest.files.remove(&beatmapset_id);
+                report.corrupt.push((beatmapset_id, filename));
+            }
+        }
+    }
+
+    // Refreshed timestamps of intact files make the next run fast again
+    if !read_only {
+        manifest.save(output_dir)?;
+    }
+
+    Ok(report)
+}
+
+/// Move a corrupt file into `quarantine/` next to a `.reason.txt` describing the problem,
+/// so it can be attached to a mirror bug report
+fn quarantine(output_dir: &Path, beatmapset_id: u32, filename: &str, reason: &str) -> Result<PathBuf> {
+    let quarantine_dir = output_dir.join(QUARANTINE_DIR);
+    std::fs::create_dir_all(&quarantine_dir)?;
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+
+    // Files in layout subfolders are quarantined flat, keeping earlier copies of the same file
+    let name = Path::new(filename).file_name().map_or(filename.into(), |name| name.to_string_lossy());
+    let mut target = quarantine_dir.join(&*name);
+    if target.exists() {
+        target = quarantine_dir.join(format!("{}-{}", timestamp, name));
+    }
+
+    std::fs::rename(output_dir.join(filename), &target)?;
+
+    let mut reason_path = target.as_os_str().to_owned();
+    reason_path.push(".reason.txt");
+    std::fs::write(
+        reason_path,
+        format!(
+            "beatmapset: {}\nfile: {}\nreason: {}\nquarantined: {}\n",
+            beatmapset_id,
+            filename,
+            reason,
+            crate::collector::date_from_unix(timestamp as i64),
+        ),
+    )?;
+
+    Ok(target)
+}
+
+/// Download fresh copies of quarantined sets and record them in the manifest
+pub async fn redownload_corrupt(config: &Config, output_dir: &Path, report: &mut VerifyReport) -> Result<()> {
+    if report.corrupt.is_empty() {
+        return Ok(());
+    }
+
+    println!("\nRe-downloading {} quarantined beatmapsets...", report.corrupt.len());
+
+    let profile = Profile::new(false);
+    let client = downloader::create_download_client(&profile)?;
+    let mirrors = config.mirrors();
+    let archives = config.archives();
+    let limits = RateLimits::new(config.download.mirror_limits);
+    let ctx = DownloadContext {
+        client: &client,
+        mirrors: &mirrors,
+        preferred_mirror: &AtomicUsize::new(0),
+        limits: &limits,
+        archives: &archives,
+        output_dir,
+        // Quarantined files were taken out of the folder, so fresh copies go back there
+        sink: &sink::DirectorySink,
+        skip_existing: false,
+        auto_overwrite: true,
+        interactive: false,
+        file_exists_default: config.download.file_exists_default,
+        extension: &config.download.extension,
+        hash: config.manifest.hash,
+        deadline: config.download.deadline(),
+        file_mode: config.download.file_mode()?,
+        metadata_collection: None,
+        owner: config.download.owner()?,
+        shutdown: Arc::new(AtomicBool::new(false)),
+        profile: &profile,
+    };
+
+    let mut manifest = Manifest::load(output_dir)?;
+
+    // Slot prefix and layout subfolder of each set, so fresh copies get the same path
+    let mut saved: HashMap<u32, Beatmapset> = collection::load_collection_metadata(output_dir)
+        .map(|collection| collection.beatmapsets.into_iter().map(|set| (set.id, set)).collect())
+        .unwrap_or_default();
+
+    for (beatmapset_id, filename) in &report.corrupt {
+        let mut beatmapset = saved.remove(beatmapset_id).unwrap_or(Beatmapset {
+            id: *beatmapset_id,
+            beatmaps: Vec::new(),
+            artist: None,
+            title: None,
+            date_added: None,
+            status: None,
+            slot: None,
+            subfolder: None,
+        });
+        // collection.json of older versions has no subfolders, the recorded path still does
+        if beatmapset.subfolder.is_none() {
+            beatmapset.subfolder = Path::new(&**filename).parent()
+                .and_then(|parent| parent.to_str())
+                .filter(|parent| !parent.is_empty())
+                .map(Into::into);
+        }
+
+        match downloader::download_beatmap(&ctx, &beatmapset).await {
+            Ok(DownloadResult::Success(filename, hash, _)) => {
+                manifest.record_hashed(output_dir, *beatmapset_id, &filename, config.manifest.hash, &hash)?;
+                report.repaired.push(*beatmapset_id);
+                println!("\x1b[32m✓\x1b[0m Re-downloaded: {}", filename);
+            }
+            Ok(DownloadResult::NotFound) => {
+                println!("\x1b[31m✗\x1b[0m {}: Not found on any mirror (404)", beatmapset_id);
+            }
+            Ok(DownloadResult::Failed(reason)) => println!("\x1b[31m✗\x1b[0m {}: {}", beatmapset_id, reason),
+            Ok(DownloadResult::FailedDynamic(reason)) => println!("\x1b[31m✗\x1b[0m {}: {}", beatmapset_id, reason),
+            Ok(_) => {}
+            Err(e) => println!("\x1b[31m✗\x1b[0m {}: {}", beatmapset_id, e),
+        }
+    }
+
+    manifest.save(output_dir)
+}
+
+/// Print the verification result
+pub fn print_report(report: &VerifyReport) {
+    for (id, filename) in &report.missing {
+        println!("\x1b[31m✗\x1b[0m Missing: {} ({})", filename, id);
+    }
+    for (id, filename) in &report.corrupt {
+        if !report.repaired.contains(id) {
+            println!("\x1b[31m✗\x1b[0m Corrupt: {} ({})", filename, id);
+        }
+    }
+
+    println!("\n================================");
+    println!("Verify summary:");
+    println!("\x1b[32m✓\x1b[0m Verified: {}", report.ok);
+    println!("\x1b[32m✓\x1b[0m Unchanged (not re-hashed): {}", report.unchanged);
+    if report.remote > 0 {
+        println!("\x1b[33m⚠\x1b[0m Uploaded to WebDAV (not checked): {}", report.remote);
+    }
+    println!("\x1b[31m✗\x1b[0m Missing: {}", report.missing.len());
+    println!("\x1b[31m✗\x1b[0m Corrupt: {}", report.corrupt.len());
+    if !report.corrupt.is_empty() {
+        println!("\x1b[32m✓\x1b[0m Re-downloaded: {}", report.repaired.len());
+    }
+
+    if report.incomplete {
+        println!("\x1b[33m⚠\x1b[0m The last download run did not finish, collection.db may be outdated (download the collection again to rebuild it)");
+    }
+}
+
+/// Single Nagios-style status line with performance data
+pub fn print_nagios(report: &VerifyReport) {
+    let health = report.health();
+    let corrupt = report.corrupt.len() - report.repaired.len();
+
+    println!(
+        "OSU-COLLECT VERIFY {} - {} ok, {} missing, {} corrupt{} | ok={} unchanged={} remote={} missing={} corrupt={}",
+        health.label(),
+        report.ok + report.unchanged,
+        report.missing.len(),
+        corrupt,
+        if report.incomplete { ", last run incomplete" } else { "" },
+        report.ok,
+        report.unchanged,
+        report.remote,
+        report.missing.len(),
+        corrupt,
+    );
+}
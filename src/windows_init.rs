@@ -26,3 +26,128 @@ pub fn enable_ansi_support() {
 
 #[cfg(not(windows))]
 pub fn enable_ansi_support() {}
+
+#[cfg(windows)]
+use std::ffi::c_void;
+#[cfg(windows)]
+use windows_sys::core::{GUID, HRESULT};
+#[cfg(windows)]
+use windows_sys::Win32::Foundation::HWND;
+#[cfg(windows)]
+use windows_sys::Win32::System::Com::{
+    CoCreateInstance, CoInitializeEx, CoUninitialize, CLSCTX_INPROC_SERVER,
+    COINIT_APARTMENTTHREADED,
+};
+#[cfg(windows)]
+use windows_sys::Win32::System::Console::GetConsoleWindow;
+#[cfg(windows)]
+use windows_sys::Win32::UI::Shell::{
+    TaskbarList, TBPFLAG, TBPF_ERROR, TBPF_NOPROGRESS, TBPF_NORMAL, TBPF_PAUSED,
+};
+
+#[cfg(windows)]
+const IID_ITASKBARLIST3: GUID = GUID::from_u128(0xea1afb91_9e28_4b86_90e9_9e9f8a5eefaf);
+
+/// Leading part of the ITaskbarList3 vtable, up to the methods used here
+#[cfg(windows)]
+#[repr(C)]
+struct TaskbarListVtbl {
+    query_interface: usize,
+    add_ref: usize,
+    release: unsafe extern "system" fn(*mut c_void) -> u32,
+    hr_init: unsafe extern "system" fn(*mut c_void) -> HRESULT,
+    add_tab: usize,
+    delete_tab: usize,
+    activate_tab: usize,
+    set_active_alt: usize,
+    mark_fullscreen_window: usize,
+    set_progress_value: unsafe extern "system" fn(*mut c_void, HWND, u64, u64) -> HRESULT,
+    set_progress_state: unsafe extern "system" fn(*mut c_void, HWND, TBPFLAG) -> HRESULT,
+}
+
+#[cfg(windows)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TaskbarState {
+    Normal,
+    Paused,
+    Error,
+}
+
+/// Overall download progress shown on the console's taskbar button
+#[cfg(windows)]
+pub struct TaskbarProgress {
+    hwnd: HWND,
+    list: *mut c_void,
+}
+
+#[cfg(windows)]
+impl TaskbarProgress {
+    /// Returns `None` when there is no console window or the taskbar is unavailable
+    pub fn new() -> Option<Self> {
+        unsafe {
+            let hwnd = GetConsoleWindow();
+            if hwnd.is_null() {
+                return None;
+            }
+
+            if CoInitializeEx(std::ptr::null(), COINIT_APARTMENTTHREADED as u32) < 0 {
+                return None;
+            }
+
+            let mut list: *mut c_void = std::ptr::null_mut();
+            let hr = CoCreateInstance(
+                &TaskbarList,
+                std::ptr::null_mut(),
+                CLSCTX_INPROC_SERVER,
+                &IID_ITASKBARLIST3,
+                &mut list,
+            );
+
+            if hr < 0 || list.is_null() {
+                CoUninitialize();
+                return None;
+            }
+
+            let taskbar = TaskbarProgress { hwnd, list };
+            if (taskbar.vtbl().hr_init)(list) < 0 {
+                return None;
+            }
+
+            Some(taskbar)
+        }
+    }
+
+    pub fn set_value(&self, completed: u64, total: u64) {
+        unsafe {
+            (self.vtbl().set_progress_value)(self.list, self.hwnd, completed, total);
+        }
+    }
+
+    pub fn set_state(&self, state: TaskbarState) {
+        let flag = match state {
+            TaskbarState::Normal => TBPF_NORMAL,
+            TaskbarState::Paused => TBPF_PAUSED,
+            TaskbarState::Error => TBPF_ERROR,
+        };
+
+        unsafe {
+            (self.vtbl().set_progress_state)(self.list, self.hwnd, flag);
+        }
+    }
+
+    #[inline]
+    unsafe fn vtbl(&self) -> &TaskbarListVtbl {
+        unsafe { &**(self.list as *const *const TaskbarListVtbl) }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for TaskbarProgress {
+    fn drop(&mut self) {
+        unsafe {
+            (self.vtbl().set_progress_state)(self.list, self.hwnd, TBPF_NOPROGRESS);
+            (self.vtbl().release)(self.list);
+            CoUninitialize();
+        }
+    }
+}
@@ -0,0 +1,352 @@
+use crate::error::{AppError, Result};
+use crate::utils::format_timestamp;
+use std::collections::HashSet;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_SIGNATURE: u32 = 0x06054b50;
+const ZIP64_END_SIGNATURE: u32 = 0x06064b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+const END_SIZE: usize = 22;
+const ZIP64_END_SIZE: usize = 56;
+const ZIP64_LOCATOR_SIZE: usize = 20;
+/// Names are always UTF-8
+const FLAG_UTF8: u16 = 0x0800;
+/// Version 2.0 for plain stored entries, 4.5 once zip64 fields are needed
+const VERSION_DEFAULT: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+
+/// CRC-32 (IEEE) lookup table
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xedb88320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Continue a CRC-32 over `bytes`, start with 0
+pub fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!crc, |crc, &b| CRC_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+/// Central directory of an archive, read from its end records
+#[derive(Debug, Default, PartialEq)]
+struct Directory {
+    entries: u64,
+    size: u64,
+    offset: u64,
+}
+
+/// Names of the entries of a zip archive, empty when there's none
+pub fn entries(archive: &Path) -> Result<HashSet<String>> {
+    let mut file = match std::fs::File::open(archive) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let directory = read_directory(&mut file)?;
+    let mut central = vec![0u8; directory.size as usize];
+    file.seek(SeekFrom::Start(directory.offset))?;
+    file.read_exact(&mut central)?;
+
+    let mut names = HashSet::new();
+    let mut rest = &central[..];
+    for _ in 0..directory.entries {
+        if rest.len() < 46 || u32_at(rest, 0) != CENTRAL_HEADER_SIGNATURE {
+            return Err(damaged(archive));
+        }
+        let name_len = u16_at(rest, 28) as usize;
+        let entry_len = 46 + name_len + u16_at(rest, 30) as usize + u16_at(rest, 32) as usize;
+        if rest.len() < entry_len {
+            return Err(damaged(archive));
+        }
+
+        names.insert(String::from_utf8_lossy(&rest[46..46 + name_len]).into_owned());
+        rest = &rest[entry_len..];
+    }
+
+    Ok(names)
+}
+
+/// Append a file as a stored (uncompressed) entry, `.osz` files are zip archives already so
+/// compressing them again gains nothing. The central directory is read, the entry written in
+/// its place and the directory written again after it, with zip64 records once the archive
+/// outgrows the classic 4 GB / 65535 entry limits.
+pub fn append(archive: &Path, source: &Path, name: &str, crc: u32, size: u64) -> Result<()> {
+    if size >= u32::MAX as u64 {
+        return Err(AppError::other("Files of 4 GB or more can't be added to the zip archive"));
+    }
+
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(archive)?;
+
+    let directory = if file.metadata()?.len() == 0 {
+        Directory::default()
+    } else {
+        read_directory(&mut file).map_err(|_| damaged(archive))?
+    };
+
+    let mut central = vec![0u8; directory.size as usize];
+    file.seek(SeekFrom::Start(directory.offset))?;
+    file.read_exact(&mut central)?;
+
+    let (time, date) = dos_datetime(SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()));
+    let name = name.as_bytes();
+    let header_offset = directory.offset;
+    let zip64 = header_offset >= u32::MAX as u64;
+    let version = if zip64 { VERSION_ZIP64 } else { VERSION_DEFAULT };
+
+    let mut local = Vec::with_capacity(30 + name.len());
+    put_u32(&mut local, LOCAL_HEADER_SIGNATURE);
+    put_u16(&mut local, version);
+    put_u16(&mut local, FLAG_UTF8);
+    put_u16(&mut local, 0); // stored
+    put_u16(&mut local, time);
+    put_u16(&mut local, date);
+    put_u32(&mut local, crc);
+    put_u32(&mut local, size as u32);
+    put_u32(&mut local, size as u32);
+    put_u16(&mut local, name.len() as u16);
+    put_u16(&mut local, 0);
+    local.extend_from_slice(name);
+
+    file.seek(SeekFrom::Start(header_offset))?;
+    file.write_all(&local)?;
+    let copied = std::io::copy(&mut std::fs::File::open(source)?, &mut file)?;
+    if copied != size {
+        return Err(AppError::other_dynamic(
+            format!("{} changed while it was added to the zip archive", source.display()).into_boxed_str()
+        ));
+    }
+
+    put_u32(&mut central, CENTRAL_HEADER_SIGNATURE);
+    put_u16(&mut central, VERSION_ZIP64);
+    put_u16(&mut central, version);
+    put_u16(&mut central, FLAG_UTF8);
+    put_u16(&mut central, 0);
+    put_u16(&mut central, time);
+    put_u16(&mut central, date);
+    put_u32(&mut central, crc);
+    put_u32(&mut central, size as u32);
+    put_u32(&mut central, size as u32);
+    put_u16(&mut central, name.len() as u16);
+    put_u16(&mut central, if zip64 { 12 } else { 0 });
+    put_u16(&mut central, 0); // comment
+    put_u16(&mut central, 0); // disk
+    put_u16(&mut central, 0); // internal attributes
+    put_u32(&mut central, 0); // external attributes
+    put_u32(&mut central, if zip64 { u32::MAX } else { header_offset as u32 });
+    central.extend_from_slice(name);
+    if zip64 {
+        put_u16(&mut central, 0x0001);
+        put_u16(&mut central, 8);
+        central.extend_from_slice(&header_offset.to_le_bytes());
+    }
+
+    let updated = Directory {
+        entries: directory.entries + 1,
+        size: central.len() as u64,
+        offset: header_offset + local.len() as u64 + size,
+    };
+    file.write_all(&central)?;
+    file.write_all(&end_records(&updated))?;
+
+    let end = file.stream_position()?;
+    file.set_len(end)?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+/// End of central directory record, preceded by the zip64 record and locator when a value
+/// doesn't fit the classic fields
+fn end_records(directory: &Directory) -> Vec<u8> {
+    let zip64 = directory.entries >= u16::MAX as u64
+        || directory.size >= u32::MAX as u64
+        || directory.offset >= u32::MAX as u64;
+    let mut records = Vec::with_capacity(ZIP64_END_SIZE + ZIP64_LOCATOR_SIZE + END_SIZE);
+
+    if zip64 {
+        let zip64_offset = directory.offset + directory.size;
+        put_u32(&mut records, ZIP64_END_SIGNATURE);
+        records.extend_from_slice(&(ZIP64_END_SIZE as u64 - 12).to_le_bytes());
+        put_u16(&mut records, VERSION_ZIP64);
+        put_u16(&mut records, VERSION_ZIP64);
+        put_u32(&mut records, 0);
+        put_u32(&mut records, 0);
+        records.extend_from_slice(&directory.entries.to_le_bytes());
+        records.extend_from_slice(&directory.entries.to_le_bytes());
+        records.extend_from_slice(&directory.size.to_le_bytes());
+        records.extend_from_slice(&directory.offset.to_le_bytes());
+
+        put_u32(&mut records, ZIP64_LOCATOR_SIGNATURE);
+        put_u32(&mut records, 0);
+        records.extend_from_slice(&zip64_offset.to_le_bytes());
+        put_u32(&mut records, 1);
+    }
+
+    let entries = directory.entries.min(u16::MAX as u64) as u16;
+    put_u32(&mut records, END_SIGNATURE);
+    put_u16(&mut records, 0);
+    put_u16(&mut records, 0);
+    put_u16(&mut records, entries);
+    put_u16(&mut records, entries);
+    put_u32(&mut records, directory.size.min(u32::MAX as u64) as u32);
+    put_u32(&mut records, directory.offset.min(u32::MAX as u64) as u32);
+    put_u16(&mut records, 0);
+
+    records
+}
+
+/// Find the central directory from the end records, following the zip64 locator when needed
+fn read_directory<R: Read + Seek>(reader: &mut R) -> std::io::Result<Directory> {
+    let invalid = || std::io::Error::new(std::io::ErrorKind::InvalidData, "no zip end of central directory record");
+
+    let len = reader.seek(SeekFrom::End(0))?;
+    // The record is followed by a comment of up to 65535 bytes
+    let tail_len = len.min((END_SIZE + u16::MAX as usize) as u64);
+    let mut tail = vec![0u8; tail_len as usize];
+    reader.seek(SeekFrom::Start(len - tail_len))?;
+    reader.read_exact(&mut tail)?;
+
+    let end = (0..tail.len().saturating_sub(END_SIZE - 1))
+        .rev()
+        .find(|&pos| u32_at(&tail, pos) == END_SIGNATURE && pos + END_SIZE + u16_at(&tail, pos + 20) as usize == tail.len())
+        .ok_or_else(invalid)?;
+
+    let directory = Directory {
+        entries: u16_at(&tail, end + 10) as u64,
+        size: u32_at(&tail, end + 12) as u64,
+        offset: u32_at(&tail, end + 16) as u64,
+    };
+    if directory.entries != u16::MAX as u64 && directory.size != u32::MAX as u64 && directory.offset != u32::MAX as u64 {
+        return Ok(directory);
+    }
+
+    let locator = end.checked_sub(ZIP64_LOCATOR_SIZE).ok_or_else(invalid)?;
+    if u32_at(&tail, locator) != ZIP64_LOCATOR_SIGNATURE {
+        return Err(invalid());
+    }
+
+    let mut record = [0u8; ZIP64_END_SIZE];
+    reader.seek(SeekFrom::Start(u64_at(&tail, locator + 8)))?;
+    reader.read_exact(&mut record)?;
+    if u32_at(&record, 0) != ZIP64_END_SIGNATURE {
+        return Err(invalid());
+    }
+
+    Ok(Directory {
+        entries: u64_at(&record, 32),
+        size: u64_at(&record, 40),
+        offset: u64_at(&record, 48),
+    })
+}
+
+/// MS-DOS time and date of a unix time, in UTC
+fn dos_datetime(secs: u64) -> (u16, u16) {
+    let timestamp = format_timestamp(secs as i64);
+    let field = |range: std::ops::Range<usize>| timestamp[range].parse::<u16>().unwrap_or(0);
+
+    let time = (field(11..13) << 11) | (field(14..16) << 5) | (field(17..19) / 2);
+    // DOS dates start in 1980
+    let date = (field(0..4).saturating_sub(1980) << 9) | (field(5..7) << 5) | field(8..10);
+    (time, date)
+}
+
+fn damaged(archive: &Path) -> AppError {
+    AppError::other_dynamic(format!("{} is damaged, it has no valid zip directory", archive.display()).into_boxed_str())
+}
+
+#[inline]
+fn put_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+#[inline]
+fn put_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+#[inline]
+fn u16_at(bytes: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([bytes[pos], bytes[pos + 1]])
+}
+
+#[inline]
+fn u32_at(bytes: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap())
+}
+
+#[inline]
+fn u64_at(bytes: &[u8], pos: usize) -> u64 {
+    u64::from_le_bytes(bytes[pos..pos + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_matches_check_value() {
+        assert_eq!(crc32(0, b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xcbf43926);
+    }
+
+    #[test]
+    fn append_adds_entries_to_new_and_existing_archives() {
+        let dir = std::env::temp_dir().join(format!("osu-collect-zip-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("beatmaps.zip");
+        let source = dir.join("source");
+
+        for (name, contents) in [("1 a.osz", &b"PK first"[..]), ("Artist/2 b.osz", &b"PK second file"[..])] {
+            std::fs::write(&source, contents).unwrap();
+            append(&archive, &source, name, crc32(0, contents), contents.len() as u64).unwrap();
+        }
+
+        let names = entries(&archive).unwrap();
+        assert_eq!(names, HashSet::from(["1 a.osz".to_string(), "Artist/2 b.osz".to_string()]));
+
+        let bytes = std::fs::read(&archive).unwrap();
+        let directory = read_directory(&mut std::io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(directory.entries, 2);
+        assert_eq!(directory.offset + directory.size + END_SIZE as u64, bytes.len() as u64);
+        assert_eq!(u32_at(&bytes, 0), LOCAL_HEADER_SIGNATURE);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn end_records_switch_to_zip64() {
+        // More entries than the classic record holds, the zip64 record follows the directory
+        let directory = Directory { entries: 70_000, size: 4, offset: 12 };
+        let mut bytes = vec![0u8; 16];
+        bytes.extend_from_slice(&end_records(&directory));
+
+        assert_eq!(u32_at(&bytes, 16), ZIP64_END_SIGNATURE);
+        assert_eq!(read_directory(&mut std::io::Cursor::new(&bytes)).unwrap(), directory);
+    }
+
+    #[test]
+    fn dos_datetime_encodes_utc() {
+        // 2024-02-29T23:59:59Z
+        assert_eq!(dos_datetime(1_709_251_199), ((23 << 11) | (59 << 5) | 29, (44 << 9) | (2 << 5) | 29));
+    }
+}